@@ -0,0 +1,185 @@
+use ndarray::ArrayView2;
+
+use crate::detector::{ChangePoint, ChangePointDetector, Detection};
+use crate::gaps::skip_missing;
+use crate::matrix_ops::{calc_diff_matrix, calc_prefix_matrix, prefix_sum_square};
+use crate::qhat::calc_q;
+use crate::util::get_windows;
+
+const DEFAULT_PENALTY: f64 = 0.0;
+
+/// E-Agglomerative change point detection.
+///
+/// Starting from a fine segmentation, adjacent segments are merged bottom-up, each time
+/// picking the merge that leaves the largest goodness-of-fit: the sum of the energy
+/// distances between neighbouring segments. The segmentation with the best fit seen along
+/// the way, less `penalty` for every change point it contains, is reported.
+pub struct EAgglomerative {
+    penalty: f64,
+}
+
+impl Default for EAgglomerative {
+    fn default() -> EAgglomerative {
+        EAgglomerative {
+            penalty: DEFAULT_PENALTY,
+        }
+    }
+}
+
+impl EAgglomerative {
+    pub fn new(penalty: f64) -> EAgglomerative {
+        EAgglomerative { penalty }
+    }
+
+    /// Detect change points starting from a segmentation where every point is its own segment.
+    pub fn get_change_points(&self, series: &[f64]) -> Vec<usize> {
+        let initial: Vec<usize> = (1..series.len()).collect();
+        self.get_change_points_from(series, &initial)
+    }
+
+    /// Detect change points starting from the segments delimited by `initial`. Only those
+    /// boundaries can be reported, so natural boundaries such as releases can be supplied here.
+    pub fn get_change_points_from(&self, series: &[f64], initial: &[usize]) -> Vec<usize> {
+        self.detect_from(series, initial).indexes()
+    }
+
+    /// Merge the segments delimited by `initial`, leaving out missing points given as NaN.
+    /// A boundary just after missing points moves to the next point present.
+    fn detect_from(&self, series: &[f64], initial: &[usize]) -> Detection {
+        let (values, indices) = skip_missing(series);
+        let initial: Vec<usize> = initial
+            .iter()
+            .map(|boundary| indices.partition_point(|i| i < boundary))
+            .collect();
+
+        Detection::new(self.merge_segments(&values, &initial)).at_indices(&indices)
+    }
+
+    /// Change points of the best segmentation, scored by the energy distance between the
+    /// segments on either side of them.
+    fn merge_segments(&self, series: &[f64], initial: &[usize]) -> Vec<ChangePoint> {
+        let series_len = series.len();
+        let mut initial: Vec<usize> = initial
+            .iter()
+            .cloned()
+            .filter(|&i| i > 0 && i < series_len)
+            .collect();
+        initial.sort_unstable();
+        initial.dedup();
+
+        let diff_matrix = calc_diff_matrix(series);
+        let prefix = calc_prefix_matrix(&diff_matrix.view());
+        let prefix = prefix.view();
+
+        let mut bounds = get_windows(&initial, series_len);
+        let mut fit = goodness_of_fit(&prefix, &bounds);
+        let mut best_score = fit - self.penalty * initial.len() as f64;
+        let mut best_bounds = bounds.clone();
+
+        while bounds.len() > 2 {
+            let (merge_at, new_fit) = (1..bounds.len() - 1)
+                .map(|k| (k, fit + merge_delta(&prefix, &bounds, k)))
+                .fold((0, f64::NEG_INFINITY), |best, candidate| {
+                    if candidate.1 > best.1 {
+                        candidate
+                    } else {
+                        best
+                    }
+                });
+            bounds.remove(merge_at);
+            fit = new_fit;
+
+            let score = fit - self.penalty * (bounds.len() - 2) as f64;
+            if score > best_score {
+                best_score = score;
+                best_bounds = bounds.clone();
+            }
+        }
+
+        best_bounds
+            .windows(3)
+            .map(|w| ChangePoint {
+                index: w[1],
+                score: energy_distance(&prefix, w[0], w[1], w[2]),
+                pvalue: None,
+            })
+            .collect()
+    }
+}
+
+impl ChangePointDetector for EAgglomerative {
+    fn detect(&self, series: &[f64]) -> Detection {
+        let initial: Vec<usize> = (1..series.len()).collect();
+        self.detect_from(series, &initial)
+    }
+}
+
+/// Energy distance between the adjacent segments `[a, b)` and `[b, c)`.
+fn energy_distance(prefix: &ArrayView2<f64>, a: usize, b: usize, c: usize) -> f64 {
+    let cross_term = prefix_sum_square(prefix, a, b, b, c);
+    let x_term = prefix_sum_square(prefix, a, b, a, b) / 2.0;
+    let y_term = prefix_sum_square(prefix, b, c, b, c) / 2.0;
+
+    calc_q(cross_term, x_term, y_term, b - a, c - b)
+}
+
+fn goodness_of_fit(prefix: &ArrayView2<f64>, bounds: &[usize]) -> f64 {
+    bounds
+        .windows(3)
+        .map(|w| energy_distance(prefix, w[0], w[1], w[2]))
+        .sum()
+}
+
+/// Change in goodness-of-fit from removing the boundary `bounds[k]`.
+fn merge_delta(prefix: &ArrayView2<f64>, bounds: &[usize], k: usize) -> f64 {
+    let first = k.saturating_sub(2);
+    let last = (k + 2).min(bounds.len() - 1);
+
+    let before = goodness_of_fit(prefix, &bounds[first..=last]);
+    let mut merged = bounds[first..=last].to_vec();
+    merged.remove(k - first);
+    let after = goodness_of_fit(prefix, &merged);
+
+    after - before
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn step_series() -> Vec<f64> {
+        let mut series: Vec<f64> = (0..20).map(|i| (i % 3) as f64).collect();
+        series.extend((0..20).map(|i| 10.0 + (i % 3) as f64));
+        series.extend((0..20).map(|i| (i % 3) as f64));
+        series
+    }
+
+    #[test]
+    fn test_merge_delta() {
+        let series = step_series();
+        let diff_matrix = calc_diff_matrix(&series);
+        let prefix = calc_prefix_matrix(&diff_matrix.view());
+        let bounds = vec![0, 10, 20, 30, 40, 60];
+
+        for k in 1..bounds.len() - 1 {
+            let mut merged = bounds.clone();
+            merged.remove(k);
+            let expected = goodness_of_fit(&prefix.view(), &merged)
+                - goodness_of_fit(&prefix.view(), &bounds);
+            assert!((merge_delta(&prefix.view(), &bounds, k) - expected).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_get_change_points() {
+        let e_agglo = EAgglomerative::default();
+        assert_eq!(e_agglo.get_change_points(&step_series()), vec![20, 40]);
+    }
+
+    #[test]
+    fn test_get_change_points_from_initial_segmentation() {
+        let e_agglo = EAgglomerative::default();
+        let initial = vec![10, 25, 40, 50];
+        assert_eq!(e_agglo.get_change_points_from(&step_series(), &initial), vec![25, 40]);
+    }
+}
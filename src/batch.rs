@@ -0,0 +1,172 @@
+use std::collections::BTreeMap;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::mpsc::{channel, sync_channel, Receiver};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::detector::{ChangePointDetector, Detection};
+
+/// The outcome of running a detector over one series of a batch.
+#[derive(PartialEq, Clone, Debug)]
+pub struct BatchResult<N> {
+    pub name: N,
+    /// The detection, or the message of the panic that aborted it.
+    pub detection: Result<Detection, String>,
+}
+
+/// Runs a detector over many series on a pool of threads.
+///
+/// Series are pulled from the input only as threads free up, and at most a couple of results
+/// per thread wait to be handed back, so memory stays bounded however long the batch is.
+/// Results are handed back in input order, and a series whose detection panics only fails
+/// that series.
+pub struct Batch<D> {
+    detector: D,
+    threads: usize,
+}
+
+impl<D: ChangePointDetector + Sync> Batch<D> {
+    pub fn new(detector: D) -> Batch<D> {
+        let threads = thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1);
+        Batch { detector, threads }
+    }
+
+    pub fn with_threads(mut self, threads: usize) -> Batch<D> {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// Detect change points in every named series, calling `on_result` with each result in
+    /// the order the series were given.
+    pub fn run<N, I, F>(&self, series: I, mut on_result: F)
+    where
+        N: Send,
+        I: IntoIterator<Item = (N, Vec<f64>)>,
+        F: FnMut(BatchResult<N>),
+    {
+        let capacity = 2 * self.threads;
+        let (job_sender, job_receiver) = sync_channel::<(usize, N, Vec<f64>)>(self.threads);
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+        let (result_sender, result_receiver) = channel();
+
+        thread::scope(|scope| {
+            for _ in 0..self.threads {
+                let jobs = Arc::clone(&job_receiver);
+                let results = result_sender.clone();
+                scope.spawn(move || {
+                    while let Some((position, name, series)) = next_job(&jobs) {
+                        let detection = catch_unwind(AssertUnwindSafe(|| {
+                            self.detector.detect(&series)
+                        }))
+                        .map_err(|panic| panic_message(&*panic));
+                        if results.send((position, BatchResult { name, detection })).is_err() {
+                            return;
+                        }
+                    }
+                });
+            }
+            drop(result_sender);
+
+            let mut series = series.into_iter().enumerate();
+            let mut pending = BTreeMap::new();
+            let mut next_position = 0;
+            let mut in_flight = 0;
+            loop {
+                while in_flight < capacity {
+                    match series.next() {
+                        Some((position, (name, values))) => {
+                            job_sender.send((position, name, values)).unwrap();
+                            in_flight += 1;
+                        }
+                        None => break,
+                    }
+                }
+                if in_flight == 0 {
+                    break;
+                }
+
+                let (position, result) = result_receiver.recv().unwrap();
+                pending.insert(position, result);
+                while let Some(result) = pending.remove(&next_position) {
+                    on_result(result);
+                    next_position += 1;
+                    in_flight -= 1;
+                }
+            }
+            drop(job_sender);
+        });
+    }
+}
+
+fn next_job<T>(jobs: &Mutex<Receiver<T>>) -> Option<T> {
+    jobs.lock().unwrap().recv().ok()
+}
+
+fn panic_message(panic: &(dyn std::any::Any + Send)) -> String {
+    if let Some(message) = panic.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = panic.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "detection panicked".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::detector::ChangePoint;
+
+    /// Reports the first point above 5, and panics on an empty series.
+    struct FirstAboveFive;
+
+    impl ChangePointDetector for FirstAboveFive {
+        fn detect(&self, series: &[f64]) -> Detection {
+            assert!(!series.is_empty(), "empty series");
+            let change_points = series
+                .iter()
+                .position(|v| *v > 5.0)
+                .map(|index| ChangePoint { index, score: 1.0, pvalue: None });
+            Detection::new(change_points.into_iter().collect())
+        }
+    }
+
+    #[test]
+    fn test_results_are_in_order() {
+        let series = (0..50).map(|i| {
+            let mut values = vec![0.0; 60];
+            values[i] = 9.0;
+            (i, values)
+        });
+        let mut results = vec![];
+        Batch::new(FirstAboveFive)
+            .with_threads(4)
+            .run(series, |result| results.push(result));
+
+        assert_eq!(results.len(), 50);
+        for (i, result) in results.iter().enumerate() {
+            assert_eq!(result.name, i);
+            assert_eq!(result.detection.as_ref().unwrap().indexes(), vec![i]);
+        }
+    }
+
+    #[test]
+    fn test_failures_are_isolated() {
+        let series = vec![
+            ("a", vec![0.0, 9.0]),
+            ("empty", vec![]),
+            ("b", vec![0.0, 0.0, 9.0]),
+        ];
+        let mut results = vec![];
+        Batch::new(FirstAboveFive)
+            .with_threads(2)
+            .run(series, |result| results.push(result));
+
+        let names: Vec<&str> = results.iter().map(|r| r.name).collect();
+        assert_eq!(names, vec!["a", "empty", "b"]);
+        assert_eq!(results[1].detection, Err("empty series".to_string()));
+        assert_eq!(results[2].detection.as_ref().unwrap().indexes(), vec![2]);
+    }
+}
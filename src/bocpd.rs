@@ -0,0 +1,415 @@
+use std::f64::consts::PI;
+
+use crate::detector::{ChangePoint, ChangePointDetector, Detection};
+use crate::gaps::skip_missing;
+
+const DEFAULT_HAZARD: f64 = 1.0 / 250.0;
+const DEFAULT_THRESHOLD: f64 = 0.5;
+const DEFAULT_MAX_RUN_LENGTH: usize = 1000;
+
+/// Normal-gamma prior over the mean and precision of each segment.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub struct NormalGamma {
+    pub mean: f64,
+    pub kappa: f64,
+    pub alpha: f64,
+    pub beta: f64,
+}
+
+impl Default for NormalGamma {
+    fn default() -> NormalGamma {
+        NormalGamma {
+            mean: 0.0,
+            kappa: 1.0,
+            alpha: 1.0,
+            beta: 1.0,
+        }
+    }
+}
+
+impl NormalGamma {
+    /// A weakly informative prior centred on the given sample of values.
+    pub fn from_sample(values: &[f64]) -> NormalGamma {
+        let n = values.len().max(1) as f64;
+        let mean = values.iter().sum::<f64>() / n;
+        let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n;
+
+        NormalGamma {
+            mean,
+            kappa: 1.0,
+            alpha: 1.0,
+            beta: variance.max(f64::EPSILON),
+        }
+    }
+
+    fn update(&self, value: f64) -> NormalGamma {
+        NormalGamma {
+            mean: (self.kappa * self.mean + value) / (self.kappa + 1.0),
+            kappa: self.kappa + 1.0,
+            alpha: self.alpha + 0.5,
+            beta: self.beta
+                + self.kappa * (value - self.mean).powi(2) / (2.0 * (self.kappa + 1.0)),
+        }
+    }
+
+    /// Density of `value` under the Student-t posterior predictive distribution.
+    fn predictive(&self, value: f64) -> f64 {
+        let dof = 2.0 * self.alpha;
+        let scale2 = self.beta * (self.kappa + 1.0) / (self.alpha * self.kappa);
+        let z2 = (value - self.mean).powi(2) / scale2;
+
+        let log_density = ln_gamma((dof + 1.0) / 2.0)
+            - ln_gamma(dof / 2.0)
+            - 0.5 * (dof * PI * scale2).ln()
+            - (dof + 1.0) / 2.0 * (1.0 + z2 / dof).ln();
+        log_density.exp()
+    }
+}
+
+/// Lanczos approximation of the log gamma function.
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 6] = [
+        76.18009172947146,
+        -86.50532032941677,
+        24.01409824083091,
+        -1.231739572450155,
+        0.1208650973866179e-2,
+        -0.5395239384953e-5,
+    ];
+    let tmp = x + 5.5 - (x + 0.5) * (x + 5.5).ln();
+    let series: f64 = COEFFICIENTS
+        .iter()
+        .enumerate()
+        .map(|(i, c)| c / (x + 1.0 + i as f64))
+        .sum();
+
+    -tmp + (2.5066282746310005 * (1.000000000190015 + series) / x).ln()
+}
+
+/// Bayesian online change point detection (Adams & MacKay) with a constant hazard and
+/// normally distributed segments.
+pub struct Bocpd {
+    hazard: f64,
+    prior: Option<NormalGamma>,
+    threshold: f64,
+    max_run_length: usize,
+}
+
+impl Default for Bocpd {
+    fn default() -> Bocpd {
+        Bocpd {
+            hazard: DEFAULT_HAZARD,
+            prior: None,
+            threshold: DEFAULT_THRESHOLD,
+            max_run_length: DEFAULT_MAX_RUN_LENGTH,
+        }
+    }
+}
+
+impl Bocpd {
+    /// `hazard` is the prior probability of a change at any point, one over the expected
+    /// segment length.
+    pub fn new(hazard: f64) -> Bocpd {
+        Bocpd {
+            hazard,
+            ..Bocpd::default()
+        }
+    }
+
+    /// Use a fixed prior for every segment. Without one, offline detection builds the prior
+    /// from the series itself.
+    pub fn with_prior(mut self, prior: NormalGamma) -> Bocpd {
+        self.prior = Some(prior);
+        self
+    }
+
+    /// Change probability at or above which `get_change_points` reports a change.
+    pub fn with_threshold(mut self, threshold: f64) -> Bocpd {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Longest run length tracked, so that each update costs at most this much however long
+    /// the series. Longer runs are merged into the last one, which then stands for every run
+    /// at least this long, and changes further back than this can no longer be placed.
+    pub fn with_max_run_length(mut self, max_run_length: usize) -> Bocpd {
+        self.max_run_length = max_run_length.max(1);
+        self
+    }
+
+    /// Start a stream that can be updated one point at a time, fed the points of `warm_up`
+    /// first. Without a fixed prior, the prior is built from the warm-up points, the way
+    /// offline detection builds it from the whole series, so they should show the usual level
+    /// and spread of the data.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is neither a fixed prior nor a warm-up point to build one from.
+    pub fn stream(&self, warm_up: &[f64]) -> BocpdStream {
+        let (values, _) = skip_missing(warm_up);
+        assert!(
+            self.prior.is_some() || !values.is_empty(),
+            "Expected a prior or warm-up points to build one from"
+        );
+        let prior = self.prior.unwrap_or_else(|| NormalGamma::from_sample(&values));
+        let mut stream = BocpdStream::new(self.hazard, prior, self.max_run_length);
+        for value in &values {
+            stream.update(*value);
+        }
+        stream
+    }
+
+    /// Probability that a new segment started at each index of `series`. Missing points,
+    /// given as NaN, are skipped and never start a segment.
+    pub fn change_probabilities(&self, series: &[f64]) -> Vec<f64> {
+        let (values, indices) = skip_missing(series);
+        let prior = self.prior.unwrap_or_else(|| NormalGamma::from_sample(&values));
+        let mut stream = BocpdStream::new(self.hazard, prior, self.max_run_length);
+        let mut settled = Vec::with_capacity(values.len());
+        for value in &values {
+            settled.extend(stream.advance(*value));
+        }
+        settled.extend(stream.change_probabilities());
+
+        let mut probabilities = vec![0.0; series.len()];
+        for (index, p) in indices.iter().zip(settled) {
+            probabilities[*index] = p;
+        }
+        probabilities
+    }
+
+    pub fn get_change_points(&self, series: &[f64]) -> Vec<usize> {
+        self.detect(series).indexes()
+    }
+}
+
+impl ChangePointDetector for Bocpd {
+    fn detect(&self, series: &[f64]) -> Detection {
+        let change_points = self
+            .change_probabilities(series)
+            .iter()
+            .enumerate()
+            .filter(|(_, p)| **p >= self.threshold)
+            .map(|(index, p)| ChangePoint {
+                index,
+                score: *p,
+                pvalue: None,
+            })
+            .collect();
+
+        Detection::new(change_points)
+    }
+}
+
+/// State of a streaming run of `Bocpd`. Missing points, given as NaN, leave the stream as it
+/// was and are not counted, so indexes count the points seen.
+pub struct BocpdStream {
+    hazard: f64,
+    prior: NormalGamma,
+    max_run_length: usize,
+    /// Posterior over the current run length: entry `r` is the probability that the latest
+    /// `r` points belong to the current segment, with `r = 0` meaning the next point starts one.
+    run_lengths: Vec<f64>,
+    posteriors: Vec<NormalGamma>,
+    /// Change probabilities of the latest points, those a run length can still reach.
+    change_probabilities: Vec<f64>,
+    settled: usize,
+}
+
+impl BocpdStream {
+    fn new(hazard: f64, prior: NormalGamma, max_run_length: usize) -> BocpdStream {
+        BocpdStream {
+            hazard,
+            prior,
+            max_run_length,
+            run_lengths: vec![1.0],
+            posteriors: vec![prior],
+            change_probabilities: vec![],
+            settled: 0,
+        }
+    }
+
+    /// Add the next point of the series and return the probability that it starts a new
+    /// segment, given the points seen so far. A missing point is skipped and has none.
+    pub fn update(&mut self, value: f64) -> f64 {
+        if value.is_nan() {
+            return 0.0;
+        }
+        self.advance(value);
+        *self.change_probabilities.last().unwrap()
+    }
+
+    /// Add the next point, returning the change probability of the oldest point held if it
+    /// is now too far back for any run length to reach.
+    fn advance(&mut self, value: f64) -> Option<f64> {
+        let index = self.settled + self.change_probabilities.len();
+        let predictive: Vec<f64> = self
+            .posteriors
+            .iter()
+            .zip(&self.run_lengths)
+            .map(|(posterior, p)| p * posterior.predictive(value))
+            .collect();
+
+        let mut run_lengths = vec![predictive.iter().sum::<f64>() * self.hazard];
+        run_lengths.extend(predictive.iter().map(|p| p * (1.0 - self.hazard)));
+        let mut posteriors = vec![self.prior];
+        posteriors.extend(self.posteriors.iter().map(|posterior| posterior.update(value)));
+        if run_lengths.len() > self.max_run_length + 1 {
+            // Fold the longest run into the capped one, keeping the posterior with more data.
+            let longest = run_lengths.pop().unwrap();
+            run_lengths[self.max_run_length] += longest;
+            posteriors.swap_remove(self.max_run_length);
+        }
+        self.posteriors = posteriors;
+
+        let total: f64 = run_lengths.iter().sum();
+        self.run_lengths = if total > 0.0 {
+            run_lengths.iter().map(|p| p / total).collect()
+        } else {
+            let mut reset = vec![0.0; run_lengths.len()];
+            reset[1] = 1.0;
+            reset
+        };
+
+        // A run length of r means the last r points, this one included, share a segment. The
+        // capped run length may stand for longer runs, so it does not place a change.
+        self.change_probabilities.push(0.0);
+        let placed = self.run_lengths.iter().take(self.max_run_length);
+        for (run_length, p) in placed.enumerate().skip(1) {
+            let start = index + 1 - run_length;
+            let held = &mut self.change_probabilities[start - self.settled];
+            if start > 0 && *p > *held {
+                *held = *p;
+            }
+        }
+
+        if self.change_probabilities.len() <= self.max_run_length {
+            return None;
+        }
+        self.settled += 1;
+        Some(self.change_probabilities.remove(0))
+    }
+
+    /// Posterior over the current run length. Its last entry covers every run at least as long
+    /// as the maximum run length once that is reached.
+    pub fn run_length_posterior(&self) -> &[f64] {
+        &self.run_lengths
+    }
+
+    /// For the latest points, up to the maximum run length of them, the highest posterior
+    /// probability observed that a new segment started there. The first of them is point
+    /// `settled()`: older points can no longer change and are dropped. The first point never
+    /// counts as a change.
+    pub fn change_probabilities(&self) -> &[f64] {
+        &self.change_probabilities
+    }
+
+    /// Number of points whose change probability can no longer change and has been dropped.
+    pub fn settled(&self) -> usize {
+        self.settled
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::noise;
+
+    #[test]
+    fn test_ln_gamma() {
+        assert!((ln_gamma(1.0)).abs() < 1e-9);
+        assert!((ln_gamma(5.0) - 24.0_f64.ln()).abs() < 1e-9);
+        assert!((ln_gamma(0.5) - PI.sqrt().ln()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_run_length_posterior_is_normalized() {
+        let mut stream = Bocpd::default().with_prior(NormalGamma::default()).stream(&[]);
+        for i in 0..50 {
+            stream.update(noise(i));
+            let total: f64 = stream.run_length_posterior().iter().sum();
+            assert!((total - 1.0).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_run_length_is_capped() {
+        let series: Vec<f64> = (0..300)
+            .map(|i| noise(i) + if i < 260 { 0.0 } else { 4.0 })
+            .collect();
+        let bocpd = Bocpd::new(1.0 / 100.0).with_max_run_length(50);
+        let mut stream = bocpd.stream(&series[..1]);
+        for value in &series[1..] {
+            stream.update(*value);
+            let total: f64 = stream.run_length_posterior().iter().sum();
+            assert!((total - 1.0).abs() < 1e-9);
+            assert!(stream.run_length_posterior().len() <= 51);
+            assert!(stream.change_probabilities().len() <= 51);
+        }
+
+        assert_eq!(stream.run_length_posterior().len(), 51);
+        assert_eq!(stream.settled() + stream.change_probabilities().len(), 300);
+        assert_eq!(bocpd.get_change_points(&series), vec![260]);
+    }
+
+    #[test]
+    fn test_get_change_points() {
+        let series: Vec<f64> = (0..120)
+            .map(|i| noise(i) + if (40..80).contains(&i) { 6.0 } else { 0.0 })
+            .collect();
+        let bocpd = Bocpd::new(1.0 / 100.0);
+
+        assert_eq!(bocpd.get_change_points(&series), vec![40, 80]);
+    }
+
+    #[test]
+    fn test_stream_matches_offline() {
+        let series: Vec<f64> = (0..60)
+            .map(|i| noise(i) + if i < 30 { 0.0 } else { 4.0 })
+            .collect();
+        let bocpd = Bocpd::default().with_prior(NormalGamma::default());
+        let mut stream = bocpd.stream(&[]);
+        for value in &series {
+            stream.update(*value);
+        }
+
+        assert_eq!(stream.change_probabilities(), &bocpd.change_probabilities(&series)[..]);
+    }
+
+    #[test]
+    fn test_stream_prior_from_warm_up() {
+        let series: Vec<f64> = (0..200)
+            .map(|i| 1000.0 + noise(i) + if i < 100 { 0.0 } else { 50.0 })
+            .collect();
+        let bocpd = Bocpd::default();
+        let mut stream = bocpd.stream(&series[..50]);
+        for value in &series[50..] {
+            stream.update(*value);
+        }
+        let changes: Vec<usize> = stream
+            .change_probabilities()
+            .iter()
+            .enumerate()
+            .filter(|(_, p)| **p >= 0.5)
+            .map(|(i, _)| i + stream.settled())
+            .collect();
+
+        assert_eq!(bocpd.get_change_points(&series), vec![100]);
+        assert_eq!(changes, vec![100]);
+    }
+
+    #[test]
+    fn test_stream_skips_missing_points() {
+        let bocpd = Bocpd::default().with_prior(NormalGamma::default());
+        let mut stream = bocpd.stream(&[1.0, 1.2]);
+        let mut gappy = bocpd.stream(&[1.0, f64::NAN, 1.2]);
+        assert_eq!(gappy.update(f64::NAN), 0.0);
+        for _ in 0..6 {
+            stream.update(1.0);
+            gappy.update(1.0);
+        }
+
+        assert_eq!(gappy.run_length_posterior(), stream.run_length_posterior());
+        assert_eq!(gappy.change_probabilities(), stream.change_probabilities());
+    }
+}
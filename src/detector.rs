@@ -0,0 +1,88 @@
+/// A change point reported by a detector.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub struct ChangePoint {
+    /// Index of the first point after the change.
+    pub index: usize,
+    /// Strength of the change as measured by the detector. Scores are only comparable
+    /// between change points reported by the same kind of detector.
+    pub score: f64,
+    /// Probability of a change at least this strong appearing by chance, for detectors
+    /// that test significance.
+    pub pvalue: Option<f64>,
+}
+
+/// The result of running a detector over a series.
+///
+/// Every index in a detection refers to the series as given to the detector, whatever
+/// points were dropped, filled or excluded along the way.
+#[derive(PartialEq, Clone, Debug, Default)]
+pub struct Detection {
+    /// Detected change points, ordered by index.
+    pub change_points: Vec<ChangePoint>,
+    /// Indexes of points flagged as outliers before detection, in order.
+    pub outliers: Vec<usize>,
+    /// Indexes of change points that are significant but change the level of the series by
+    /// less than the minimum effect size, in order.
+    pub small_effects: Vec<usize>,
+}
+
+impl Detection {
+    pub fn new(mut change_points: Vec<ChangePoint>) -> Detection {
+        change_points.sort_by_key(|cp| cp.index);
+        Detection {
+            change_points,
+            outliers: vec![],
+            small_effects: vec![],
+        }
+    }
+
+    /// Move change points found among the points left after dropping missing ones to the
+    /// index of those points in the whole series.
+    pub(crate) fn at_indices(mut self, indices: &[usize]) -> Detection {
+        for cp in self.change_points.iter_mut() {
+            cp.index = indices[cp.index];
+        }
+        self
+    }
+
+    pub fn with_outliers(mut self, outliers: Vec<usize>) -> Detection {
+        self.outliers = outliers;
+        self
+    }
+
+    pub fn with_small_effects(mut self, mut small_effects: Vec<usize>) -> Detection {
+        small_effects.sort_unstable();
+        self.small_effects = small_effects;
+        self
+    }
+
+    pub fn indexes(&self) -> Vec<usize> {
+        self.change_points.iter().map(|cp| cp.index).collect()
+    }
+}
+
+/// Common interface of the change point detection algorithms, so they can be swapped or run
+/// side by side over the same series.
+///
+/// NaN marks a missing point. Detectors leave missing points out of the detection, which
+/// still indexes the whole series as described on [`Detection`].
+pub trait ChangePointDetector {
+    fn detect(&self, series: &[f64]) -> Detection;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detection_is_ordered_by_index() {
+        let change_points = vec![
+            ChangePoint { index: 30, score: 2.0, pvalue: None },
+            ChangePoint { index: 10, score: 5.0, pvalue: None },
+            ChangePoint { index: 20, score: 1.0, pvalue: None },
+        ];
+        let detection = Detection::new(change_points);
+
+        assert_eq!(detection.indexes(), vec![10, 20, 30]);
+    }
+}
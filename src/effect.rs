@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+
+use crate::util::{get_windows, median};
+
+/// The measure of a segment's level used to size a change.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum Location {
+    Mean,
+    Median,
+}
+
+impl Location {
+    fn of(&self, segment: &[f64]) -> f64 {
+        match self {
+            Location::Mean => segment.iter().sum::<f64>() / segment.len() as f64,
+            Location::Median => median(segment),
+        }
+    }
+}
+
+/// What to do with significant change points whose change in level is too small to act on.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum EffectAction {
+    /// Drop them, measuring every change between the segments found by the detector.
+    Drop,
+    /// Drop them one at a time, smallest first, measuring the remaining changes between the
+    /// segments left once the dropped boundaries are merged back.
+    Merge,
+    /// Keep them, but report them as too small.
+    Mark,
+}
+
+/// Minimum change in segment level for a change point to be reported.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub struct MinEffect {
+    location: Location,
+    absolute: f64,
+    relative: f64,
+    action: EffectAction,
+}
+
+impl MinEffect {
+    pub fn new(location: Location) -> MinEffect {
+        MinEffect {
+            location,
+            absolute: 0.0,
+            relative: 0.0,
+            action: EffectAction::Drop,
+        }
+    }
+
+    /// Minimum absolute difference between the levels either side of a change point.
+    pub fn with_absolute(mut self, absolute: f64) -> MinEffect {
+        self.absolute = absolute;
+        self
+    }
+
+    /// Minimum difference between the levels either side of a change point, as a fraction of
+    /// the level before it.
+    pub fn with_relative(mut self, relative: f64) -> MinEffect {
+        self.relative = relative;
+        self
+    }
+
+    pub fn with_action(mut self, action: EffectAction) -> MinEffect {
+        self.action = action;
+        self
+    }
+
+    pub fn action(&self) -> EffectAction {
+        self.action
+    }
+
+    /// The change points of a series, given as indexes in any order, that are too small,
+    /// in the order given. Indexes that do not split the series in two, such as 0 or its
+    /// length, are never too small.
+    pub fn too_small(&self, series: &[f64], change_points: &[usize]) -> Vec<usize> {
+        match self.action {
+            EffectAction::Drop | EffectAction::Mark => {
+                let sizes = self.sizes(series, change_points);
+                change_points
+                    .iter()
+                    .filter(|cp| self.is_too_small(sizes.get(cp)))
+                    .copied()
+                    .collect()
+            }
+            EffectAction::Merge => {
+                let mut kept = change_points.to_vec();
+                loop {
+                    let sizes = self.sizes(series, &kept);
+                    let smallest = kept
+                        .iter()
+                        .filter(|cp| self.is_too_small(sizes.get(cp)))
+                        .min_by(|a, b| sizes[a].0.partial_cmp(&sizes[b].0).unwrap());
+                    match smallest {
+                        Some(cp) => {
+                            let cp = *cp;
+                            kept.retain(|k| *k != cp);
+                        }
+                        None => break,
+                    }
+                }
+                change_points
+                    .iter()
+                    .filter(|cp| !kept.contains(cp))
+                    .copied()
+                    .collect()
+            }
+        }
+    }
+
+    /// Absolute and relative change in level at each change point, between the segments
+    /// either side of it.
+    fn sizes(&self, series: &[f64], change_points: &[usize]) -> HashMap<usize, (f64, f64)> {
+        get_windows(change_points, series.len())
+            .windows(3)
+            .filter(|w| w[0] < w[1] && w[1] < w[2])
+            .map(|w| {
+                let before = self.location.of(&series[w[0]..w[1]]);
+                let after = self.location.of(&series[w[1]..w[2]]);
+                let absolute = (after - before).abs();
+                let relative = if before != 0.0 {
+                    absolute / before.abs()
+                } else {
+                    f64::INFINITY
+                };
+                (w[1], (absolute, relative))
+            })
+            .collect()
+    }
+
+    fn is_too_small(&self, size: Option<&(f64, f64)>) -> bool {
+        size.is_some_and(|(absolute, relative)| {
+            *absolute < self.absolute || *relative < self.relative
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn steps() -> Vec<f64> {
+        let mut series = vec![100.0; 10];
+        series.extend(vec![100.2; 10]);
+        series.extend(vec![110.0; 10]);
+        series
+    }
+
+    #[test]
+    fn test_relative_threshold() {
+        let min_effect = MinEffect::new(Location::Mean).with_relative(0.01);
+        assert_eq!(min_effect.too_small(&steps(), &[20, 10]), vec![10]);
+    }
+
+    #[test]
+    fn test_absolute_threshold() {
+        let min_effect = MinEffect::new(Location::Median).with_absolute(5.0);
+        assert_eq!(min_effect.too_small(&steps(), &[10, 20]), vec![10]);
+    }
+
+    #[test]
+    fn test_merge_measures_merged_segments() {
+        // Two small steps up that add up to a large one once either is merged away.
+        let mut series = vec![0.0; 10];
+        series.extend(vec![0.8; 10]);
+        series.extend(vec![1.6; 10]);
+        let drop = MinEffect::new(Location::Mean).with_absolute(1.0);
+        let merge = drop.with_action(EffectAction::Merge);
+
+        assert_eq!(drop.too_small(&series, &[10, 20]), vec![10, 20]);
+        assert_eq!(merge.too_small(&series, &[10, 20]), vec![10]);
+    }
+
+    #[test]
+    fn test_indexes_outside_the_series() {
+        let min_effect = MinEffect::new(Location::Mean).with_absolute(1.0);
+        assert!(min_effect.too_small(&[1.0, 2.0, 3.0], &[3]).is_empty());
+        assert!(min_effect.too_small(&[1.0, 2.0, 3.0], &[0]).is_empty());
+        let merge = min_effect.with_action(EffectAction::Merge);
+        assert!(merge.too_small(&[1.0, 2.0], &[0, 2]).is_empty());
+    }
+}
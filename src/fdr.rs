@@ -0,0 +1,65 @@
+/// Procedures controlling the false discovery rate across many tests.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum FdrMethod {
+    /// Benjamini–Hochberg, valid for independent or positively dependent tests.
+    BenjaminiHochberg,
+    /// Benjamini–Yekutieli, valid under any dependence between tests but more conservative.
+    BenjaminiYekutieli,
+}
+
+impl FdrMethod {
+    /// Adjusted p-values, in the order given. Rejecting every test whose adjusted p-value is
+    /// at most `q` keeps the false discovery rate at or below `q`.
+    pub fn adjust(&self, pvalues: &[f64]) -> Vec<f64> {
+        let m = pvalues.len();
+        let correction = match self {
+            FdrMethod::BenjaminiHochberg => 1.0,
+            FdrMethod::BenjaminiYekutieli => (1..=m).map(|k| 1.0 / k as f64).sum(),
+        };
+
+        let mut order: Vec<usize> = (0..m).collect();
+        order.sort_by(|a, b| pvalues[*a].partial_cmp(&pvalues[*b]).unwrap());
+
+        let mut adjusted = vec![0.0; m];
+        let mut running_min: f64 = 1.0;
+        for (rank, i) in order.iter().enumerate().rev() {
+            let scaled = pvalues[*i] * correction * m as f64 / (rank + 1) as f64;
+            running_min = running_min.min(scaled);
+            adjusted[*i] = running_min;
+        }
+
+        adjusted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: &[f64], expected: &[f64]) {
+        assert_eq!(actual.len(), expected.len());
+        for (a, e) in actual.iter().zip(expected) {
+            assert!((a - e).abs() < 1e-12, "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn test_benjamini_hochberg() {
+        let adjusted = FdrMethod::BenjaminiHochberg.adjust(&[0.01, 0.04, 0.03, 0.005]);
+        assert_close(&adjusted, &[0.02, 0.04, 0.04, 0.02]);
+    }
+
+    #[test]
+    fn test_benjamini_yekutieli() {
+        let adjusted = FdrMethod::BenjaminiYekutieli.adjust(&[0.01, 0.04, 0.03, 0.005]);
+        let c = 1.0 + 1.0 / 2.0 + 1.0 / 3.0 + 1.0 / 4.0;
+        assert_close(&adjusted, &[0.02 * c, 0.04 * c, 0.04 * c, 0.02 * c]);
+    }
+
+    #[test]
+    fn test_adjusted_pvalues_are_capped() {
+        assert_close(&FdrMethod::BenjaminiHochberg.adjust(&[0.9, 0.8]), &[0.9, 0.9]);
+        assert_close(&FdrMethod::BenjaminiYekutieli.adjust(&[0.9, 0.8]), &[1.0, 1.0]);
+        assert!(FdrMethod::BenjaminiHochberg.adjust(&[]).is_empty());
+    }
+}
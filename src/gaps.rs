@@ -0,0 +1,120 @@
+/// What to do with points that are missing from a series, such as runs where a benchmark
+/// failed or was skipped.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum GapPolicy {
+    /// Leave missing points out of the detection.
+    Skip,
+    /// Fill missing points by linear interpolation between their nearest neighbours.
+    Interpolate,
+    /// Fill missing points with the last value seen before them.
+    CarryForward,
+}
+
+impl GapPolicy {
+    /// The values to run detection over and the index in `series` of each of them. Gaps at
+    /// the edges of the series take the nearest value seen.
+    pub fn apply(&self, series: &[Option<f64>]) -> (Vec<f64>, Vec<usize>) {
+        let known: Vec<(usize, f64)> = series
+            .iter()
+            .enumerate()
+            .filter_map(|(i, v)| v.map(|v| (i, v)))
+            .collect();
+        if known.is_empty() {
+            return (vec![], vec![]);
+        }
+
+        match self {
+            GapPolicy::Skip => known.iter().map(|(i, v)| (*v, *i)).unzip(),
+            GapPolicy::CarryForward => {
+                let mut last = known[0].1;
+                let values = series
+                    .iter()
+                    .map(|v| {
+                        if let Some(v) = v {
+                            last = *v;
+                        }
+                        last
+                    })
+                    .collect();
+                (values, (0..series.len()).collect())
+            }
+            GapPolicy::Interpolate => {
+                let mut next = 0;
+                let values = (0..series.len())
+                    .map(|i| {
+                        while next < known.len() && known[next].0 < i {
+                            next += 1;
+                        }
+                        if next == known.len() {
+                            return known[next - 1].1;
+                        }
+                        let (after_index, after) = known[next];
+                        if after_index == i || next == 0 {
+                            return after;
+                        }
+                        let (before_index, before) = known[next - 1];
+                        let fraction =
+                            (i - before_index) as f64 / (after_index - before_index) as f64;
+                        before + fraction * (after - before)
+                    })
+                    .collect();
+                (values, (0..series.len()).collect())
+            }
+        }
+    }
+}
+
+/// Treat NaN values in a series as missing.
+pub fn nan_as_missing(series: &[f64]) -> Vec<Option<f64>> {
+    series
+        .iter()
+        .map(|v| if v.is_nan() { None } else { Some(*v) })
+        .collect()
+}
+
+/// The values of a series that are not NaN, and the index in the series of each of them.
+pub fn skip_missing(series: &[f64]) -> (Vec<f64>, Vec<usize>) {
+    GapPolicy::Skip.apply(&nan_as_missing(series))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn series() -> Vec<Option<f64>> {
+        vec![None, Some(1.0), None, None, Some(4.0), None]
+    }
+
+    #[test]
+    fn test_skip() {
+        assert_eq!(GapPolicy::Skip.apply(&series()), (vec![1.0, 4.0], vec![1, 4]));
+    }
+
+    #[test]
+    fn test_interpolate() {
+        let (values, indices) = GapPolicy::Interpolate.apply(&series());
+        assert_eq!(values, vec![1.0, 1.0, 2.0, 3.0, 4.0, 4.0]);
+        assert_eq!(indices, vec![0, 1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn test_carry_forward() {
+        let (values, _) = GapPolicy::CarryForward.apply(&series());
+        assert_eq!(values, vec![1.0, 1.0, 1.0, 1.0, 4.0, 4.0]);
+    }
+
+    #[test]
+    fn test_all_missing() {
+        assert_eq!(GapPolicy::Interpolate.apply(&[None, None]), (vec![], vec![]));
+    }
+
+    #[test]
+    fn test_skip_missing() {
+        assert_eq!(skip_missing(&[f64::NAN, 2.0, f64::NAN, 3.0]), (vec![2.0, 3.0], vec![1, 3]));
+    }
+
+    #[test]
+    fn test_nan_as_missing() {
+        assert_eq!(nan_as_missing(&[1.0, f64::NAN]), vec![Some(1.0), None]);
+    }
+}
@@ -0,0 +1,83 @@
+use ndarray::{Array2, ArrayView2};
+
+/// Kernel used to compare points in kernel change point detection.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum Kernel {
+    /// `exp(-d^2 / (2 h^2))`
+    Gaussian,
+    /// `exp(-d / h)`
+    Laplacian,
+}
+
+/// How the kernel bandwidth `h` is chosen.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum Bandwidth {
+    /// The median distance between distinct points of the series.
+    Median,
+    Fixed(f64),
+}
+
+impl Kernel {
+    fn evaluate(&self, distance: f64, bandwidth: f64) -> f64 {
+        match self {
+            Kernel::Gaussian => (-distance * distance / (2.0 * bandwidth * bandwidth)).exp(),
+            Kernel::Laplacian => (-distance / bandwidth).exp(),
+        }
+    }
+}
+
+pub fn median_bandwidth(diff_matrix: &ArrayView2<f64>) -> f64 {
+    let series_len = diff_matrix.nrows();
+    let mut distances: Vec<f64> = (0..series_len)
+        .flat_map(|i| (i + 1..series_len).map(move |j| (i, j)))
+        .map(|(i, j)| diff_matrix[[i, j]])
+        .filter(|d| *d > 0.0)
+        .collect();
+    if distances.is_empty() {
+        return 1.0;
+    }
+    distances.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+    distances[distances.len() / 2]
+}
+
+/// Turn a matrix of absolute differences into kernel distances `1 - k(x, y)`. Fed to
+/// `qhat_values`, these give the (scaled) maximum mean discrepancy between the two sides of
+/// each split instead of the energy distance.
+pub fn calc_kernel_matrix(
+    diff_matrix: &ArrayView2<f64>,
+    kernel: Kernel,
+    bandwidth: Bandwidth,
+) -> Array2<f64> {
+    let bandwidth = match bandwidth {
+        Bandwidth::Median => median_bandwidth(diff_matrix),
+        Bandwidth::Fixed(bandwidth) => bandwidth,
+    };
+
+    diff_matrix.mapv(|d| 1.0 - kernel.evaluate(d, bandwidth))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix_ops::calc_diff_matrix;
+
+    #[test]
+    fn test_median_bandwidth() {
+        let diff_matrix = calc_diff_matrix(&[1.0, 2.0, 4.0, 4.0]);
+        // Distinct distances: 1, 3, 3, 2, 2 (the pair of 4s is skipped).
+        assert_eq!(median_bandwidth(&diff_matrix.view()), 2.0);
+    }
+
+    #[test]
+    fn test_calc_kernel_matrix() {
+        let diff_matrix = calc_diff_matrix(&[0.0, 1.0]);
+        let bandwidth = Bandwidth::Fixed(1.0);
+        let laplacian = calc_kernel_matrix(&diff_matrix.view(), Kernel::Laplacian, bandwidth);
+        let gaussian = calc_kernel_matrix(&diff_matrix.view(), Kernel::Gaussian, bandwidth);
+
+        assert_eq!(laplacian[[0, 0]], 0.0);
+        assert!((laplacian[[0, 1]] - (1.0 - (-1.0_f64).exp())).abs() < 1e-12);
+        assert!((gaussian[[1, 0]] - (1.0 - (-0.5_f64).exp())).abs() < 1e-12);
+    }
+}
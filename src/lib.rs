@@ -1,122 +1,1202 @@
+mod agglomerative;
+mod batch;
+mod bocpd;
+mod detector;
+mod effect;
+mod fdr;
+mod gaps;
+mod kernel;
 mod matrix_ops;
+mod median;
+mod outliers;
+mod pelt;
+mod permutation;
+mod preprocess;
+mod progress;
 mod qhat;
+mod samples;
+mod search;
+mod spacing;
+mod trend;
+mod two_sample;
 mod util;
 
+use std::ops::Range;
+use std::panic::RefUnwindSafe;
+use std::sync::Arc;
+
 use ndarray::prelude::*;
+use gaps::nan_as_missing;
+use kernel::calc_kernel_matrix;
 use matrix_ops::calc_diff_matrix;
-use qhat::{get_qhat_values, qhat_values};
-use rand::prelude::SliceRandom;
+use permutation::{containing_window, permutation_pvalue, permutation_test};
+use search::{best_split, window_intervals};
+use spacing::suppress_close;
 use util::{argmax, get_windows, maximum};
 
+pub use agglomerative::EAgglomerative;
+pub use batch::{Batch, BatchResult};
+pub use bocpd::{Bocpd, BocpdStream, NormalGamma};
+pub use detector::{ChangePoint, ChangePointDetector, Detection};
+pub use effect::{EffectAction, Location, MinEffect};
+pub use fdr::FdrMethod;
+pub use gaps::GapPolicy;
+pub use kernel::{Bandwidth, Kernel};
+pub use outliers::{OutlierAction, OutlierFilter, OutlierMethod};
+pub use pelt::{Cost, Pelt, Penalty};
+pub use permutation::{Shuffle, TestMode};
+pub use preprocess::{Preprocessed, Preprocessing, Transform};
+pub use progress::{CancellationToken, Progress};
+pub use qhat::{QhatCurve, Statistic};
+pub use samples::Samples;
+pub use search::SearchMode;
+pub use spacing::Strength;
+pub use trend::{TrendDetector, TrendSegment};
+pub use two_sample::{energy_test, EnergyTest};
+
 const DEFAULT_PVALUE: f64 = 0.01;
-const DEFAULT_PERMUTATIONS: usize = 100;
+// Enough for a change to pass the default p-value with one permutation scoring as high,
+// since the observed data counts as a permutation too.
+const DEFAULT_PERMUTATIONS: usize = 199;
+const MAX_REFINEMENT_PASSES: usize = 10;
+const PERMUTATION_BATCH_SIZE: usize = 10;
 
 pub struct EDivisive {
     pvalue: f64,
     n_permutations: usize,
+    test_mode: TestMode,
+    shuffle: Shuffle,
+    statistic: Statistic,
+    search_mode: SearchMode,
+    kernel: Option<(Kernel, Bandwidth)>,
+    gap_policy: GapPolicy,
+    preprocessing: Preprocessing,
+    outlier_filter: Option<OutlierFilter>,
+    exclusions: Vec<Range<usize>>,
+    min_effect: Option<MinEffect>,
+    min_distance: Option<(usize, Strength)>,
+    refine: bool,
+    on_progress: Option<Arc<dyn Fn(Progress) + Send + Sync + RefUnwindSafe>>,
+    cancellation: Option<CancellationToken>,
+}
+
+/// Change points of a series in the order they were found, along with the points flagged on
+/// the way.
+struct SeriesChangePoints {
+    change_points: Vec<ChangePoint>,
+    outliers: Vec<usize>,
+    small_effects: Vec<usize>,
+    /// P-value of every candidate tested, including the one that failed and ended the search.
+    tested: Vec<f64>,
 }
 
-#[derive(PartialEq, Copy, Clone, Debug)]
-struct ChangePoint {
-    index: usize,
-    qhat: f64,
+impl SeriesChangePoints {
+    fn into_detection(self) -> Detection {
+        Detection::new(self.change_points)
+            .with_outliers(self.outliers)
+            .with_small_effects(self.small_effects)
+    }
 }
 
 fn get_best_change_point(
     diff_matrix: &ArrayView2<f64>,
-    known_change_points: &Vec<ChangePoint>,
+    samples: &Samples,
+    known_change_points: &[ChangePoint],
+    intervals: &[(usize, usize)],
+    statistic: Statistic,
 ) -> ChangePoint {
-    let series_len = diff_matrix.nrows();
     let mut change_points: Vec<ChangePoint> = vec![];
 
-    let boundaries: Vec<usize> = get_windows(&cp_indexes(&known_change_points), series_len);
+    let boundaries: Vec<usize> = get_windows(&cp_indexes(known_change_points), samples.len());
     for bounds in boundaries.windows(2) {
         let a = bounds[0];
         let b = bounds[1];
 
-        let qhats = qhat_values(&diff_matrix.slice(s!(a..b, a..b)));
-        let max_idx = argmax(&qhats);
-        change_points.push(ChangePoint{index: max_idx + a, qhat: qhats[max_idx]});
+        let range = samples.sample_range(a, b);
+        let mut best = best_split(
+            &diff_matrix.slice(s!(range.clone(), range.clone())),
+            samples.weights().map(|w| &w[range]),
+            &samples.window_offsets(a, b),
+            &window_intervals(intervals, a, b),
+            statistic,
+        );
+        best.index += a;
+        change_points.push(best);
     }
 
-    let max_index = argmax(&change_points.iter().map(|cp| cp.qhat).collect());
+    let max_index = argmax(&change_points.iter().map(|cp| cp.score).collect::<Vec<f64>>());
 
     change_points[max_index]
 }
 
-fn cp_indexes(change_points: &Vec<ChangePoint>) -> Vec<usize> {
+/// Keep the points for which `keep(position, index)` holds, where `index` is the point's index
+/// in the original series.
+fn retain_points<F>(values: Vec<f64>, indices: Vec<usize>, keep: F) -> (Vec<f64>, Vec<usize>)
+where
+    F: Fn(usize, usize) -> bool,
+{
+    values
+        .into_iter()
+        .zip(indices)
+        .enumerate()
+        .filter(|(position, (_, index))| keep(*position, *index))
+        .map(|(_, point)| point)
+        .unzip()
+}
+
+fn cp_indexes(change_points: &[ChangePoint]) -> Vec<usize> {
     change_points.iter().map(|cp| cp.index).collect()
 }
 
-impl EDivisive {
-    pub fn default() -> EDivisive {
+impl Default for EDivisive {
+    fn default() -> EDivisive {
         EDivisive {
             pvalue: DEFAULT_PVALUE,
             n_permutations: DEFAULT_PERMUTATIONS,
+            test_mode: TestMode::Global,
+            shuffle: Shuffle::Points,
+            statistic: Statistic::Mean,
+            search_mode: SearchMode::Greedy,
+            kernel: None,
+            gap_policy: GapPolicy::Skip,
+            preprocessing: Preprocessing::new(),
+            outlier_filter: None,
+            exclusions: vec![],
+            min_effect: None,
+            min_distance: None,
+            refine: false,
+            on_progress: None,
+            cancellation: None,
         }
     }
+}
 
+impl EDivisive {
     pub fn new(pvalue: f64, n_permutations: usize) -> EDivisive {
         EDivisive {
             pvalue,
             n_permutations,
+            ..EDivisive::default()
         }
     }
 
-    pub fn get_change_points(&self, series: &Vec<f64>) -> Vec<usize> {
-        let diff_matrix = calc_diff_matrix(series);
+    pub fn with_test_mode(mut self, test_mode: TestMode) -> EDivisive {
+        self.test_mode = test_mode;
+        self
+    }
+
+    pub fn with_shuffle(mut self, shuffle: Shuffle) -> EDivisive {
+        self.shuffle = shuffle;
+        self
+    }
+
+    pub fn with_statistic(mut self, statistic: Statistic) -> EDivisive {
+        self.statistic = statistic;
+        self
+    }
+
+    pub fn with_search_mode(mut self, search_mode: SearchMode) -> EDivisive {
+        self.search_mode = search_mode;
+        self
+    }
+
+    /// Compare points through a kernel rather than their absolute difference, turning the
+    /// detector into kernel change point detection based on the maximum mean discrepancy.
+    /// This also picks up changes in the shape of the distribution, such as bimodality.
+    pub fn with_kernel(mut self, kernel: Kernel, bandwidth: Bandwidth) -> EDivisive {
+        self.kernel = Some((kernel, bandwidth));
+        self
+    }
+
+    /// How missing values, given as NaN or `None`, are handled. Whatever the policy, indexes
+    /// refer to the whole series, as described on [`Detection`].
+    pub fn with_gap_policy(mut self, gap_policy: GapPolicy) -> EDivisive {
+        self.gap_policy = gap_policy;
+        self
+    }
+
+    /// Transforms to run over a series, after filling its gaps, before looking for change
+    /// points. Indexes are unaffected, as described on [`Detection`], while scores are on the
+    /// transformed scale.
+    pub fn with_preprocessing(mut self, preprocessing: Preprocessing) -> EDivisive {
+        self.preprocessing = preprocessing;
+        self
+    }
+
+    /// Look for outliers after filling gaps and before preprocessing. Outliers are reported
+    /// by `detect`, and with `OutlierAction::Remove` left out of the detection altogether.
+    pub fn with_outlier_filter(mut self, outlier_filter: OutlierFilter) -> EDivisive {
+        self.outlier_filter = Some(outlier_filter);
+        self
+    }
+
+    /// Ranges of indexes known to hold garbage, such as runs from a broken build. Points in
+    /// them are left out of the detection entirely, as if they were never measured, and never
+    /// reported as change points or outliers.
+    pub fn with_exclusions(mut self, exclusions: Vec<Range<usize>>) -> EDivisive {
+        self.exclusions = exclusions;
+        self
+    }
+
+    /// Only report change points that shift the level of a series by at least this much, as
+    /// measured on the series before preprocessing.
+    pub fn with_min_effect(mut self, min_effect: MinEffect) -> EDivisive {
+        self.min_effect = Some(min_effect);
+        self
+    }
+
+    /// Keep only the stronger of any two change points fewer than `min_distance` points apart.
+    /// When any are dropped, the rest are rescored against their new neighbours and tested
+    /// again, dropping those no longer significant.
+    pub fn with_min_distance(mut self, min_distance: usize, strength: Strength) -> EDivisive {
+        self.min_distance = Some((min_distance, strength));
+        self
+    }
+
+    /// Once detection is done, move every change point to the best split of the segment
+    /// between its neighbours, repeating until none of them move.
+    pub fn with_refinement(mut self, refine: bool) -> EDivisive {
+        self.refine = refine;
+        self
+    }
+
+    /// Call `on_progress` whenever a change point is confirmed and after every batch of
+    /// permutations, so that long detections can report how far they have got.
+    pub fn with_progress<F>(mut self, on_progress: F) -> EDivisive
+    where
+        F: Fn(Progress) + Send + Sync + RefUnwindSafe + 'static,
+    {
+        self.on_progress = Some(Arc::new(on_progress));
+        self
+    }
+
+    /// Stop detection once `cancellation` is cancelled, checking before each candidate and
+    /// between batches of permutations. The change points confirmed by then are returned, and
+    /// the candidate being tested is dropped.
+    pub fn with_cancellation(mut self, cancellation: CancellationToken) -> EDivisive {
+        self.cancellation = Some(cancellation);
+        self
+    }
+
+    /// The qhat score of every split of `series[range]`, using this detector's statistic and
+    /// kernel. The series is scored as given: the gap policy, exclusions, outlier filter and
+    /// preprocessing are not applied, so on a series they would change the curve can differ
+    /// from the one detection searches. Returns `None` if the range is empty or runs past the
+    /// end of the series, or if the series holds a NaN.
+    pub fn qhat_curve(&self, series: &[f64], range: Range<usize>) -> Option<QhatCurve> {
+        if range.start >= range.end || range.end > series.len() {
+            return None;
+        }
+        if series.iter().any(|v| v.is_nan()) {
+            return None;
+        }
+        Some(QhatCurve::new(&self.distance_matrix(series).view(), range, self.statistic))
+    }
+
+    pub fn get_change_points(&self, series: &[f64]) -> Vec<usize> {
+        cp_indexes(&self.find_series_change_points(&nan_as_missing(series), None).change_points)
+    }
+
+    /// Change points of a series with missing values, handled according to the gap policy.
+    pub fn get_change_points_with_gaps(&self, series: &[Option<f64>]) -> Vec<usize> {
+        cp_indexes(&self.find_series_change_points(series, None).change_points)
+    }
+
+    /// Change points of a series holding several samples per point, such as repeated runs of
+    /// a benchmark. Distances are taken between all samples, while change points stay between
+    /// points. Points without any samples, such as commits whose runs all failed, are skipped
+    /// like gaps, and exclusions are applied; indexes are as described on [`Detection`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if a sample is NaN, or if a gap policy other than `GapPolicy::Skip`, an outlier
+    /// filter, preprocessing or a minimum effect size is set, since those work on a single
+    /// value per point.
+    pub fn get_change_points_replicated(&self, replicates: &[Vec<f64>]) -> Vec<usize> {
+        let indices: Vec<usize> = (0..replicates.len())
+            .filter(|i| !replicates[*i].is_empty())
+            .collect();
+        let measured: Vec<Vec<f64>> = indices.iter().map(|i| replicates[*i].clone()).collect();
+
+        cp_indexes(&self.find_samples_change_points(&Samples::from_replicates(&measured), &indices))
+    }
+
+    /// Change points of a series where each point carries a weight, so that low-confidence
+    /// points have proportionally less influence on where the series is split. The series goes
+    /// through exclusions, the gap policy, the outlier filter, preprocessing and the minimum
+    /// effect size like any other, and every point keeps its weight.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is not exactly one weight per point of the series.
+    pub fn get_change_points_weighted(&self, series: &[f64], weights: &[f64]) -> Vec<usize> {
+        assert_eq!(weights.len(), series.len(), "Expected one weight per point");
+        cp_indexes(
+            &self
+                .find_series_change_points(&nan_as_missing(series), Some(weights))
+                .change_points,
+        )
+    }
+
+    /// Change points of samples built by hand, applying exclusions. Samples have no way to
+    /// mark a missing value, so they must not hold NaN.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a gap policy other than `GapPolicy::Skip`, an outlier filter, preprocessing
+    /// or a minimum effect size is set, since those work on a single value per point.
+    pub fn detect_samples(&self, samples: &Samples) -> Detection {
+        let indices: Vec<usize> = (0..samples.len()).collect();
+        Detection::new(self.find_samples_change_points(samples, &indices))
+    }
+
+    /// Detect change points in every series of a batch, in order, keeping only those that
+    /// survive false discovery rate control at level `q` across the whole batch. The
+    /// detector's own p-value threshold then only screens candidates, so it should be looser
+    /// than `q`. Every candidate tested counts towards the correction, including the one
+    /// that failed the screen and ended the search in each series. Change points keep their
+    /// unadjusted p-values.
+    ///
+    /// No p-value can be smaller than `1 / (n_permutations + 1)`, while the strongest change
+    /// in a batch of `m` tests must reach `q / m`, so `n_permutations` has to grow with the
+    /// size of the batch: at least `m / q` for a lone change to be found.
+    pub fn detect_with_fdr(&self, batch: &[Vec<f64>], method: FdrMethod, q: f64) -> Vec<Detection> {
+        let found: Vec<SeriesChangePoints> = batch
+            .iter()
+            .map(|series| self.find_series_change_points(&nan_as_missing(series), None))
+            .collect();
+        let pvalues: Vec<f64> = found.iter().flat_map(|f| f.tested.iter().copied()).collect();
+        // Adjusted p-values rise with the raw ones, so the tests that survive are exactly
+        // those up to some cutoff.
+        let cutoff = pvalues
+            .iter()
+            .zip(method.adjust(&pvalues))
+            .filter(|(_, adjusted)| *adjusted <= q)
+            .map(|(pvalue, _)| *pvalue)
+            .fold(f64::NEG_INFINITY, f64::max);
+
+        found
+            .into_iter()
+            .map(|found| {
+                let mut detection = found.into_detection();
+                detection.change_points.retain(|cp| cp.pvalue.is_some_and(|p| p <= cutoff));
+                let kept = detection.indexes();
+                detection.small_effects.retain(|i| kept.contains(i));
+                detection
+            })
+            .collect()
+    }
+
+    /// Change points of a series, applying exclusions, the gap policy, the outlier filter and
+    /// preprocessing first and the minimum effect size last.
+    fn find_series_change_points(
+        &self,
+        series: &[Option<f64>],
+        weights: Option<&[f64]>,
+    ) -> SeriesChangePoints {
+        // Excluded points must not be used to fill gaps, so hide them before the gap policy
+        // and drop whatever it puts in their place afterwards.
+        let is_excluded = |i: usize| self.is_excluded(i);
+        let series: Vec<Option<f64>> = series
+            .iter()
+            .enumerate()
+            .map(|(i, v)| if is_excluded(i) { None } else { *v })
+            .collect();
+        let (values, indices) = self.gap_policy.apply(&series);
+        let (mut values, mut indices) = retain_points(values, indices, |_, i| !is_excluded(i));
+
+        let mut outliers = vec![];
+        if let Some(filter) = self.outlier_filter {
+            let found = filter.find(&values);
+            outliers = found.iter().map(|i| indices[*i]).collect();
+            if filter.action() == OutlierAction::Remove {
+                let kept = retain_points(values, indices, |p, _| found.binary_search(&p).is_err());
+                values = kept.0;
+                indices = kept.1;
+            }
+        }
+
+        let processed = self.preprocessing.apply_indexed(&values, &indices);
+        let mut samples = Samples::from_series(&processed.values);
+        if let Some(weights) = weights {
+            let kept: Vec<f64> = processed.indices.iter().map(|i| weights[*i]).collect();
+            samples = samples.with_weights(&kept);
+        }
+        let (mut change_points, failed) = self.search(&samples);
+        for cp in change_points.iter_mut() {
+            cp.index = processed.indices[cp.index];
+        }
+        let tested = change_points.iter().filter_map(|cp| cp.pvalue).chain(failed).collect();
+
+        let mut small_effects = vec![];
+        if let Some(min_effect) = self.min_effect {
+            // Effects are measured on the values before preprocessing, by position.
+            let positions: Vec<usize> = change_points
+                .iter()
+                .map(|cp| indices.binary_search(&cp.index).unwrap())
+                .collect();
+            let too_small: Vec<usize> = min_effect
+                .too_small(&values, &positions)
+                .iter()
+                .map(|p| indices[*p])
+                .collect();
+            if min_effect.action() == EffectAction::Mark {
+                small_effects = too_small;
+            } else {
+                change_points.retain(|cp| !too_small.contains(&cp.index));
+            }
+        }
+
+        SeriesChangePoints {
+            change_points,
+            outliers,
+            small_effects,
+            tested,
+        }
+    }
+
+    /// Change points of samples whose point `p` is point `indices[p]` of the whole series,
+    /// leaving out excluded points.
+    fn find_samples_change_points(&self, samples: &Samples, indices: &[usize]) -> Vec<ChangePoint> {
+        assert!(
+            self.gap_policy == GapPolicy::Skip
+                && self.outlier_filter.is_none()
+                && self.preprocessing == Preprocessing::new()
+                && self.min_effect.is_none(),
+            "The gap policy, outlier filter, preprocessing and minimum effect size need a \
+             single value per point"
+        );
+        let kept: Vec<usize> = (0..samples.len())
+            .filter(|p| !self.is_excluded(indices[*p]))
+            .collect();
+
+        let mut change_points = self.find_change_points(&samples.select(&kept));
+        for cp in change_points.iter_mut() {
+            cp.index = indices[kept[cp.index]];
+        }
+        change_points
+    }
+
+    fn is_excluded(&self, index: usize) -> bool {
+        self.exclusions.iter().any(|range| range.contains(&index))
+    }
+
+    /// Change points in the order they were found, each with its qhat and p-value.
+    fn find_change_points(&self, samples: &Samples) -> Vec<ChangePoint> {
+        self.search(samples).0
+    }
+
+    /// Change points in the order they were found, and the p-value of the candidate that
+    /// failed the test and ended the search, if one did.
+    fn search(&self, samples: &Samples) -> (Vec<ChangePoint>, Option<f64>) {
+        if samples.len() < 2 {
+            return (vec![], None);
+        }
+
+        let diff_matrix = self.distance_matrix(samples.values());
+        let intervals = self.search_mode.intervals(samples.len(), &mut rand::thread_rng());
         let mut change_points: Vec<ChangePoint> = vec![];
 
-        let mut best_candidate = get_best_change_point(&diff_matrix.view(), &change_points);
-        let mut windows = get_windows(&cp_indexes(&change_points), series.len());
-        while self.is_significant(&best_candidate, series, &windows) {
-            if change_points.contains(&best_candidate) {
+        let mut best_candidate = get_best_change_point(
+            &diff_matrix.view(),
+            samples,
+            &change_points,
+            &intervals,
+            self.statistic,
+        );
+        let mut windows = get_windows(&cp_indexes(&change_points), samples.len());
+        let mut failed = None;
+        while best_candidate.score >= 1e-9 {
+            if self.is_cancelled() {
+                break;
+            }
+            let probability = match self.probability(
+                &best_candidate,
+                &diff_matrix.view(),
+                samples,
+                &windows,
+                &intervals,
+            ) {
+                Some(probability) => probability,
+                None => break,
+            };
+            if probability > self.pvalue {
+                failed = Some(probability);
                 break;
             }
+            if change_points.iter().any(|cp| cp.index == best_candidate.index) {
+                break;
+            }
+            best_candidate.pvalue = Some(probability);
             change_points.push(best_candidate);
-            windows = get_windows(&cp_indexes(&change_points), series.len());
-            best_candidate = get_best_change_point(&diff_matrix.view(), &change_points);
+            self.report(Progress::Iteration {
+                change_points: change_points.len(),
+            });
+            windows = get_windows(&cp_indexes(&change_points), samples.len());
+            best_candidate = get_best_change_point(
+                &diff_matrix.view(),
+                samples,
+                &change_points,
+                &intervals,
+                self.statistic,
+            );
+        }
+        if self.is_cancelled() {
+            return (change_points, failed);
         }
 
-        cp_indexes(&change_points)
+        if let Some((min_distance, strength)) = self.min_distance {
+            let kept = suppress_close(&change_points, min_distance, strength);
+            if kept.len() < change_points.len() {
+                change_points = self.revalidate(kept, &diff_matrix.view(), samples, &intervals);
+            }
+        }
+        if self.refine {
+            self.refine(&mut change_points, &diff_matrix.view(), samples, &intervals);
+        }
+
+        (change_points, failed)
+    }
+
+    /// Move each change point to the best split between its neighbours until they settle or
+    /// `MAX_REFINEMENT_PASSES` passes have been made, then score and test those that moved
+    /// again at their new index. With a minimum distance set, change points only move to
+    /// splits at least that far from their neighbouring change points.
+    fn refine(
+        &self,
+        change_points: &mut [ChangePoint],
+        diff_matrix: &ArrayView2<f64>,
+        samples: &Samples,
+        intervals: &[(usize, usize)],
+    ) {
+        let unrefined = cp_indexes(change_points);
+        let min_distance = self.min_distance.map_or(1, |(min_distance, _)| min_distance.max(1));
+        for _ in 0..MAX_REFINEMENT_PASSES {
+            let mut moved = false;
+            let mut order: Vec<usize> = (0..change_points.len()).collect();
+            order.sort_by_key(|i| change_points[*i].index);
+
+            for (position, i) in order.iter().enumerate() {
+                let (a, first) = if position == 0 {
+                    (0, 1)
+                } else {
+                    (change_points[order[position - 1]].index, min_distance)
+                };
+                let (b, last) = match order.get(position + 1) {
+                    Some(next) => (change_points[*next].index, min_distance),
+                    None => (samples.len(), 1),
+                };
+                if a + first > b - last {
+                    continue;
+                }
+
+                let range = samples.sample_range(a, b);
+                let offsets = samples.window_offsets(a, b);
+                let qhats = self.statistic.values(
+                    &diff_matrix.slice(s!(range.clone(), range.clone())),
+                    samples.weights().map(|w| &w[range]),
+                );
+                let allowed: Vec<f64> = (first..=b - a - last).map(|p| qhats[offsets[p]]).collect();
+                let (best, score) = maximum(&allowed);
+
+                let cp = &mut change_points[*i];
+                if a + first + best != cp.index {
+                    cp.index = a + first + best;
+                    cp.score = score;
+                    moved = true;
+                }
+            }
+
+            if !moved {
+                break;
+            }
+        }
+
+        let windows = get_windows(&cp_indexes(change_points), samples.len());
+        for (cp, index) in change_points.iter_mut().zip(unrefined) {
+            if cp.index != index {
+                cp.pvalue = self.retest(cp, &windows, diff_matrix, samples, intervals);
+            }
+        }
+    }
+
+    /// Rescore and test every change point against the segments either side of it, dropping
+    /// the least significant of those that fail one at a time until all of them pass.
+    fn revalidate(
+        &self,
+        mut change_points: Vec<ChangePoint>,
+        diff_matrix: &ArrayView2<f64>,
+        samples: &Samples,
+        intervals: &[(usize, usize)],
+    ) -> Vec<ChangePoint> {
+        loop {
+            let windows = get_windows(&cp_indexes(&change_points), samples.len());
+            let mut cancelled = false;
+            for cp in change_points.iter_mut() {
+                match self.retest(cp, &windows, diff_matrix, samples, intervals) {
+                    Some(probability) => cp.pvalue = Some(probability),
+                    None => {
+                        cancelled = true;
+                        break;
+                    }
+                }
+            }
+            if cancelled {
+                return change_points;
+            }
+
+            let weakest = change_points
+                .iter()
+                .enumerate()
+                .filter(|(_, cp)| cp.pvalue.unwrap() > self.pvalue)
+                .max_by(|(_, a), (_, b)| a.pvalue.partial_cmp(&b.pvalue).unwrap())
+                .map(|(i, _)| i);
+            match weakest {
+                Some(i) => {
+                    change_points.remove(i);
+                }
+                None => return change_points,
+            }
+        }
+    }
+
+    /// Rescore a change point against the segments either side of it, between its neighbours
+    /// in `windows`, and return its p-value there, or `None` if detection was cancelled.
+    fn retest(
+        &self,
+        cp: &mut ChangePoint,
+        windows: &[usize],
+        diff_matrix: &ArrayView2<f64>,
+        samples: &Samples,
+        intervals: &[(usize, usize)],
+    ) -> Option<f64> {
+        let position = windows.binary_search(&cp.index).unwrap();
+        let (a, b) = (windows[position - 1], windows[position + 1]);
+        let range = samples.sample_range(a, b);
+        let qhats = self.statistic.values(
+            &diff_matrix.slice(s!(range.clone(), range.clone())),
+            samples.weights().map(|w| &w[range]),
+        );
+        cp.score = qhats[samples.sample_range(a, cp.index).len()];
+        self.probability(cp, diff_matrix, samples, &[a, b], intervals)
+    }
+
+    fn distance_matrix(&self, series: &[f64]) -> Array2<f64> {
+        let diff_matrix = calc_diff_matrix(series);
+        match self.kernel {
+            Some((kernel, bandwidth)) => {
+                calc_kernel_matrix(&diff_matrix.view(), kernel, bandwidth)
+            }
+            None => diff_matrix,
+        }
     }
 
-    fn is_significant(
+    fn probability(
         &self,
         candidate: &ChangePoint,
-        series: &Vec<f64>,
-        windows: &Vec<usize>,
-    ) -> bool {
-        if candidate.qhat < 1e-9 {
-            return false;
+        diff_matrix: &ArrayView2<f64>,
+        samples: &Samples,
+        windows: &[usize],
+        intervals: &[(usize, usize)],
+    ) -> Option<f64> {
+        let windows = match self.test_mode {
+            TestMode::Global => windows,
+            TestMode::Local => containing_window(windows, candidate.index),
+        };
+        let mut permuted = Vec::with_capacity(self.n_permutations);
+        while permuted.len() < self.n_permutations {
+            if self.is_cancelled() {
+                return None;
+            }
+            let batch = PERMUTATION_BATCH_SIZE.min(self.n_permutations - permuted.len());
+            permuted.extend((0..batch).map(|_| {
+                permutation_test(
+                    diff_matrix,
+                    samples,
+                    windows,
+                    intervals,
+                    self.shuffle,
+                    self.statistic,
+                )
+            }));
+            self.report(Progress::Permutations {
+                done: permuted.len(),
+                total: self.n_permutations,
+            });
         }
-        let permutes_with_higher = (0..self.n_permutations)
-            .map(|_| permutation_test(series, windows))
-            .filter(|v| v > &candidate.qhat)
-            .count();
-        let probability = permutes_with_higher as f64 / (self.n_permutations + 1) as f64;
 
-        probability <= self.pvalue
+        Some(permutation_pvalue(candidate.score, &permuted))
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancellation
+            .as_ref()
+            .is_some_and(|cancellation| cancellation.is_cancelled())
+    }
+
+    fn report(&self, progress: Progress) {
+        if let Some(on_progress) = &self.on_progress {
+            on_progress(progress);
+        }
     }
 }
 
-fn permutation_test(series: &Vec<f64>, windows: &Vec<usize>) -> f64 {
-    let mut rng = rand::thread_rng();
-    let mut permuted_qhat_values: Vec<f64> = vec![];
+impl ChangePointDetector for EDivisive {
+    fn detect(&self, series: &[f64]) -> Detection {
+        self.find_series_change_points(&nan_as_missing(series), None).into_detection()
+    }
+}
 
-    for bounds in windows.windows(2) {
-        let a = bounds[0];
-        let b = bounds[1];
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::noise;
+
+    /// Thirty zeros followed by thirty tens, with a change point at 30.
+    fn step_series() -> Vec<f64> {
+        let mut series = vec![0.0; 30];
+        series.extend(vec![10.0; 30]);
+        series
+    }
+
+    #[test]
+    fn test_local_test_mode() {
+        // A noisy stretch, then a jump to a quiet stretch with a small step in it. Permuting the
+        // noisy window drowns the small step under the global test, but not the local one.
+        let mut series: Vec<f64> = (0..100).map(|i| 10.0 * noise(i)).collect();
+        series.extend((0..20).map(|i| 100.0 + (i % 2) as f64 * 0.1));
+        series.extend((0..20).map(|i| 100.5 + (i % 2) as f64 * 0.1));
+        let e_divisive = EDivisive::new(0.05, 50);
+
+        assert_eq!(e_divisive.get_change_points(&series), vec![100]);
+        let e_divisive = e_divisive.with_test_mode(TestMode::Local);
+        assert_eq!(e_divisive.get_change_points(&series), vec![100, 120]);
+    }
+
+    #[test]
+    fn test_wild_search_mode() {
+        let mut series: Vec<f64> = (0..60).map(|i| (i % 5) as f64 * 0.1).collect();
+        series.extend(vec![5.0; 4]);
+        series.extend((0..60).map(|i| (i % 5) as f64 * 0.1));
+        let e_divisive = EDivisive::new(0.05, 20).with_search_mode(SearchMode::Wild(300));
+
+        assert_eq!(e_divisive.detect(&series).indexes(), vec![60, 64]);
+    }
+
+    #[test]
+    fn test_kernel_detects_change_in_shape() {
+        // Same mean and similar spread, but the second half is bimodal.
+        let mut series: Vec<f64> = (0..40).map(|i| (i % 5) as f64 * 0.5 - 1.0).collect();
+        series.extend((0..40).map(|i| if i % 2 == 0 { -1.5 } else { 1.5 }));
+        let e_divisive =
+            EDivisive::new(0.05, 200).with_kernel(Kernel::Gaussian, Bandwidth::Median);
+
+        assert_eq!(e_divisive.get_change_points(&series), vec![40]);
+    }
+
+    #[test]
+    fn test_qhat_curve() {
+        let series = step_series();
+        let e_divisive = EDivisive::default();
+        let curve = e_divisive.qhat_curve(&series, 0..series.len()).unwrap();
+
+        assert_eq!(curve.values().len(), 60);
+        assert_eq!(curve.best().0, 30);
+        assert!(curve.qhat_at(30).unwrap() > curve.qhat_at(20).unwrap());
+        assert_eq!(e_divisive.qhat_curve(&series, 20..40).unwrap().best().0, 30);
+    }
 
-        let mut window: Vec<f64> = vec![0.; b - a];
-        window.copy_from_slice(&series[a..b]);
-        window.shuffle(&mut rng);
+    #[test]
+    fn test_qhat_curve_rejects_bad_input() {
+        let mut series = step_series();
+        let e_divisive = EDivisive::default();
 
-        let q_list = get_qhat_values(&window);
-        let (_, max_qhat) = maximum(&q_list);
-        permuted_qhat_values.push(max_qhat);
+        assert_eq!(e_divisive.qhat_curve(&series, 50..70), None);
+        assert_eq!(e_divisive.qhat_curve(&series, 20..20), None);
+        series[5] = f64::NAN;
+        assert_eq!(e_divisive.qhat_curve(&series, 0..60), None);
     }
 
-    let (_, max_value) = maximum(&permuted_qhat_values);
-    max_value
+    #[test]
+    fn test_replicated_measurements() {
+        let replicates: Vec<Vec<f64>> = (0..40)
+            .map(|i| {
+                let level = if i < 25 { 1.0 } else { 2.0 };
+                vec![level - 0.3, level, level + 0.3]
+            })
+            .collect();
+        let e_divisive = EDivisive::new(0.05, 50);
+
+        assert_eq!(e_divisive.get_change_points_replicated(&replicates), vec![25]);
+    }
+
+    #[test]
+    fn test_replicated_points_without_samples() {
+        let mut replicates: Vec<Vec<f64>> = (0..40)
+            .map(|i| {
+                let level = if i < 25 { 1.0 } else { 2.0 };
+                vec![level - 0.3, level, level + 0.3]
+            })
+            .collect();
+        replicates[10].clear();
+        replicates[25].clear();
+        let e_divisive = EDivisive::new(0.05, 50);
+
+        assert_eq!(e_divisive.get_change_points_replicated(&replicates), vec![26]);
+        // Too few points left to tell a change from chance.
+        let sparse = vec![vec![1.0], vec![], vec![2.0], vec![3.0]];
+        assert!(e_divisive.get_change_points_replicated(&sparse).is_empty());
+    }
+
+    #[test]
+    fn test_weighted_observations() {
+        let mut series = step_series();
+        let mut weights = vec![1.0; 60];
+        // A burst of noisy points at the end that should barely count.
+        for i in 50..60 {
+            series[i] = if i % 2 == 0 { 40.0 } else { -20.0 };
+            weights[i] = 0.01;
+        }
+        let e_divisive = EDivisive::new(0.05, 50);
+
+        assert_eq!(e_divisive.get_change_points_weighted(&series, &weights), vec![30]);
+    }
+
+    #[test]
+    fn test_weighted_median_with_missing_points() {
+        let mut series: Vec<f64> = (0..30).map(|i| (i % 3) as f64).collect();
+        series.extend((0..30).map(|i| 10.0 + (i % 3) as f64));
+        series[45] = f64::NAN;
+        let weights = vec![1.0; 60];
+        let e_divisive = EDivisive::new(0.05, 50).with_statistic(Statistic::Median);
+
+        assert_eq!(e_divisive.get_change_points_weighted(&series, &weights), vec![30]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Expected one weight per point")]
+    fn test_weighted_rejects_mismatched_weights() {
+        EDivisive::default().get_change_points_weighted(&[1.0, 2.0, 3.0], &[1.0, 1.0]);
+    }
+
+    #[test]
+    fn test_gaps_are_reported_in_original_indexes() {
+        let mut series: Vec<Option<f64>> = vec![Some(0.0); 30];
+        series.extend(vec![Some(10.0); 30]);
+        for i in &[3, 10, 11, 12, 45] {
+            series[*i] = None;
+        }
+
+        for policy in &[GapPolicy::Skip, GapPolicy::Interpolate, GapPolicy::CarryForward] {
+            let e_divisive = EDivisive::new(0.05, 50).with_gap_policy(*policy);
+            let change_points = e_divisive.get_change_points_with_gaps(&series);
+            assert_eq!(change_points, vec![30], "{:?}", policy);
+        }
+
+        // The first point after the change is missing, so the change is reported at the next.
+        series[30] = None;
+        series[31] = None;
+        let nan_series: Vec<f64> = series.iter().map(|v| v.unwrap_or(f64::NAN)).collect();
+        assert_eq!(EDivisive::new(0.05, 50).get_change_points(&nan_series), vec![32]);
+    }
+
+    #[test]
+    fn test_preprocessing() {
+        let mut series: Vec<f64> = (0..60)
+            .map(|i| if i < 30 { 100.0 } else { 200.0 } * (1.0 + (i % 3) as f64 * 0.01))
+            .collect();
+        // Dropped by the log transform, which shifts every later point down by one.
+        series[10] = 0.0;
+        let preprocessing = Preprocessing::new()
+            .then(Transform::Log)
+            .then(Transform::ZScore);
+        let e_divisive = EDivisive::new(0.05, 50).with_preprocessing(preprocessing);
+
+        assert_eq!(e_divisive.get_change_points(&series), vec![30]);
+    }
+
+    #[test]
+    fn test_deseasonalize() {
+        let weekly = [0.0, 0.0, 0.0, 0.0, 0.0, 4.0, 4.0];
+        let series: Vec<f64> = (0..84)
+            .map(|i| weekly[i % 7] + if i < 42 { 1.0 } else { 3.0 })
+            .collect();
+        let preprocessing = Preprocessing::new().then(Transform::Deseasonalize(7));
+        let e_divisive = EDivisive::new(0.05, 50).with_preprocessing(preprocessing);
+
+        assert_eq!(e_divisive.get_change_points(&series), vec![42]);
+    }
+
+    #[test]
+    fn test_deseasonalize_with_exclusions() {
+        let weekly = [10.0, 12.0, 11.0, 13.0, 12.0, 4.0, 3.0];
+        let series: Vec<f64> = (0..140).map(|i| weekly[i % 7]).collect();
+        let preprocessing = Preprocessing::new().then(Transform::Deseasonalize(7));
+        let e_divisive = EDivisive::new(0.05, 50)
+            .with_preprocessing(preprocessing)
+            .with_exclusions(vec![20..23, 90..91]);
+
+        assert!(e_divisive.get_change_points(&series).is_empty());
+    }
+
+    #[test]
+    fn test_outlier_filter() {
+        let mut series: Vec<f64> = (0..60)
+            .map(|i| (i % 4) as f64 * 0.1 + if i < 30 { 0.0 } else { 5.0 })
+            .collect();
+        series[10] = 40.0;
+        series[11] = 40.0;
+        let filter = OutlierFilter::new(OutlierMethod::Hampel(3, 3.0), OutlierAction::Remove);
+        let e_divisive = EDivisive::new(0.05, 50).with_outlier_filter(filter);
+        let detection = e_divisive.detect(&series);
+
+        assert_eq!(detection.indexes(), vec![30]);
+        assert_eq!(detection.outliers, vec![10, 11]);
+    }
+
+    #[test]
+    fn test_exclusions() {
+        let mut series = vec![1.0; 30];
+        series.extend(vec![50.0; 5]);
+        series.extend(vec![1.0; 25]);
+        series.extend(vec![6.0; 30]);
+        series[80] = -40.0;
+        series[81] = -40.0;
+        let e_divisive = EDivisive::new(0.05, 50).with_exclusions(vec![30..35, 80..82]);
+
+        assert_eq!(e_divisive.get_change_points(&series), vec![60]);
+        let weights = vec![1.0; series.len()];
+        assert_eq!(e_divisive.get_change_points_weighted(&series, &weights), vec![60]);
+        let replicates: Vec<Vec<f64>> = series.iter().map(|v| vec![*v]).collect();
+        assert_eq!(e_divisive.get_change_points_replicated(&replicates), vec![60]);
+        let detection = e_divisive.detect_samples(&Samples::from_series(&series));
+        assert_eq!(detection.indexes(), vec![60]);
+    }
+
+    #[test]
+    #[should_panic(expected = "need a single value per point")]
+    fn test_replicated_rejects_preprocessing() {
+        let preprocessing = Preprocessing::new().then(Transform::Log);
+        EDivisive::default()
+            .with_preprocessing(preprocessing)
+            .get_change_points_replicated(&[vec![1.0], vec![2.0]]);
+    }
+
+    #[test]
+    fn test_min_effect() {
+        let mut series: Vec<f64> = (0..90).map(|i| 100.0 + (i % 3) as f64 * 0.01).collect();
+        for v in series[30..60].iter_mut() {
+            *v += 0.3;
+        }
+        for v in series[60..].iter_mut() {
+            *v += 10.0;
+        }
+        let min_effect = MinEffect::new(Location::Median).with_relative(0.01);
+        let dropping = EDivisive::new(0.05, 50).with_min_effect(min_effect);
+        let marking = EDivisive::new(0.05, 50)
+            .with_min_effect(min_effect.with_action(EffectAction::Mark));
+
+        assert_eq!(dropping.get_change_points(&series), vec![60]);
+        let detection = marking.detect(&series);
+        assert_eq!(detection.indexes(), vec![30, 60]);
+        assert_eq!(detection.small_effects, vec![30]);
+    }
+
+    #[test]
+    fn test_min_distance() {
+        let mut series: Vec<f64> = (0..30).map(|i| (i % 3) as f64 * 0.1).collect();
+        // A short blip on the way up, so the change into the upper level is the stronger one.
+        series.extend(vec![2.0, 2.0]);
+        series.extend((0..30).map(|i| 10.0 + (i % 3) as f64 * 0.1));
+        let e_divisive = EDivisive::new(0.05, 100);
+        let spaced = EDivisive::new(0.05, 100).with_min_distance(3, Strength::Score);
+
+        assert_eq!(e_divisive.detect(&series).indexes(), vec![30, 32]);
+        let detection = spaced.detect(&series);
+        assert_eq!(detection.indexes(), vec![32]);
+        assert!(detection.change_points[0].pvalue.unwrap() <= 0.05);
+    }
+
+    #[test]
+    fn test_refinement() {
+        let mut series: Vec<f64> = (0..90).map(|i| (i % 3) as f64 * 0.1).collect();
+        for v in series[30..60].iter_mut() {
+            *v += 4.0;
+        }
+        for v in series[60..].iter_mut() {
+            *v += 8.0;
+        }
+        let samples = Samples::from_series(&series);
+        let diff_matrix = calc_diff_matrix(&series);
+        let mut change_points = vec![
+            ChangePoint { index: 45, score: 1.0, pvalue: Some(0.0) },
+            ChangePoint { index: 60, score: 1.0, pvalue: Some(0.0) },
+        ];
+        EDivisive::default().refine(&mut change_points, &diff_matrix.view(), &samples, &[]);
+
+        // The moved change point is tested again at 30, where no permutation comes close.
+        assert_eq!(cp_indexes(&change_points), vec![30, 60]);
+        assert_eq!(change_points[0].pvalue, Some(1.0 / 200.0));
+        assert_eq!(change_points[1].pvalue, Some(0.0));
+        let e_divisive = EDivisive::new(0.05, 50).with_refinement(true);
+        assert_eq!(e_divisive.detect(&series).indexes(), vec![30, 60]);
+    }
+
+    #[test]
+    fn test_detect_with_fdr() {
+        let step = step_series();
+        let pattern: Vec<f64> = (0..60).map(|i| (i % 5) as f64).collect();
+        let batch = vec![pattern, step.clone(), step.clone(), step];
+        let e_divisive = EDivisive::new(0.05, 50);
+        let indexes = |q: f64| -> Vec<Vec<usize>> {
+            e_divisive
+                .detect_with_fdr(&batch, FdrMethod::BenjaminiHochberg, q)
+                .iter()
+                .map(|d| d.indexes())
+                .collect()
+        };
+
+        // Every step passes the screening, but with 50 permutations none can reach q = 0.01.
+        assert_eq!(e_divisive.detect(&batch[1]).indexes(), vec![30]);
+        assert_eq!(indexes(0.05), vec![vec![], vec![30], vec![30], vec![30]]);
+        assert_eq!(indexes(0.01), vec![vec![]; 4]);
+    }
+
+    #[test]
+    fn test_detect_with_fdr_counts_failed_candidates() {
+        let step = step_series();
+        let mut batch = vec![step.clone()];
+        batch.extend(vec![(0..60).map(|i| (i % 5) as f64).collect::<Vec<f64>>(); 9]);
+        let indexes = |n_permutations: usize| -> Vec<usize> {
+            EDivisive::new(0.05, n_permutations)
+                .detect_with_fdr(&batch, FdrMethod::BenjaminiHochberg, 0.05)[0]
+                .indexes()
+        };
+
+        // The step alone passes the screening, but the nine failed tests of the patterns also
+        // count, so its p-value has to reach 0.05 / 10.
+        assert_eq!(EDivisive::new(0.05, 99).get_change_points(&step), vec![30]);
+        assert!(indexes(99).is_empty());
+        assert_eq!(indexes(499), vec![30]);
+    }
+
+    #[test]
+    fn test_progress() {
+        let series = step_series();
+        let reports = Arc::new(std::sync::Mutex::new(vec![]));
+        let log = Arc::clone(&reports);
+        let e_divisive =
+            EDivisive::new(0.05, 25).with_progress(move |p| log.lock().unwrap().push(p));
+
+        assert_eq!(e_divisive.get_change_points(&series), vec![30]);
+        let reports = reports.lock().unwrap();
+        assert_eq!(
+            reports[..3],
+            [
+                Progress::Permutations { done: 10, total: 25 },
+                Progress::Permutations { done: 20, total: 25 },
+                Progress::Permutations { done: 25, total: 25 },
+            ]
+        );
+        assert_eq!(reports[3], Progress::Iteration { change_points: 1 });
+    }
+
+    #[test]
+    fn test_cancellation() {
+        let mut series = step_series();
+        series.extend(vec![0.0; 30]);
+        let cancellation = CancellationToken::new();
+        let token = cancellation.clone();
+        let e_divisive = EDivisive::new(0.05, 50)
+            .with_cancellation(cancellation)
+            .with_progress(move |p| {
+                if p == (Progress::Iteration { change_points: 1 }) {
+                    token.cancel();
+                }
+            });
+
+        assert_eq!(e_divisive.get_change_points(&series).len(), 1);
+
+        let cancelled = CancellationToken::new();
+        cancelled.cancel();
+        let e_divisive = EDivisive::new(0.05, 50).with_cancellation(cancelled.clone());
+        assert!(e_divisive.get_change_points(&series).is_empty());
+
+        // Without permutations nothing is checked between batches.
+        assert!(!EDivisive::new(1.0, 0).get_change_points(&series).is_empty());
+        let e_divisive = EDivisive::new(1.0, 0).with_cancellation(cancelled);
+        assert!(e_divisive.get_change_points(&series).is_empty());
+    }
+
+    #[test]
+    fn test_unwind_safe() {
+        fn assert_unwind_safe<T: std::panic::UnwindSafe + RefUnwindSafe>(_: &T) {}
+        let log = Arc::new(std::sync::Mutex::new(vec![]));
+        let e_divisive = EDivisive::default().with_progress(move |p| log.lock().unwrap().push(p));
+
+        assert_unwind_safe(&e_divisive);
+    }
+
+    #[test]
+    fn test_refinement_keeps_min_distance() {
+        let mut series: Vec<f64> = (0..60).map(|i| (i % 3) as f64 * 0.1).collect();
+        for v in series[30..].iter_mut() {
+            *v += 4.0;
+        }
+        let samples = Samples::from_series(&series);
+        let diff_matrix = calc_diff_matrix(&series);
+        let mut change_points = vec![
+            ChangePoint { index: 20, score: 1.0, pvalue: Some(0.0) },
+            ChangePoint { index: 34, score: 1.0, pvalue: Some(0.0) },
+        ];
+        let e_divisive = EDivisive::default().with_min_distance(10, Strength::Score);
+        e_divisive.refine(&mut change_points, &diff_matrix.view(), &samples, &[]);
+
+        // The first point moves towards the step at 30, but stops 10 points short of 34.
+        assert_eq!(cp_indexes(&change_points), vec![24, 34]);
+    }
+
+    #[test]
+    fn test_detect_reports_pvalues() {
+        let series = step_series();
+        let detection = EDivisive::new(0.05, 50).detect(&series);
+
+        assert_eq!(detection.indexes(), vec![30]);
+        assert!(detection.change_points[0].pvalue.unwrap() <= 0.05);
+    }
+
+    #[test]
+    fn test_block_shuffle() {
+        let series = step_series();
+        let e_divisive = EDivisive::new(0.05, 50).with_shuffle(Shuffle::Blocks(5));
+
+        assert_eq!(e_divisive.get_change_points(&series), vec![30]);
+    }
+
+    #[test]
+    fn test_series_shorter_than_block() {
+        let series: Vec<f64> = (0..40).map(|i| (i % 5) as f64).collect();
+        let e_divisive = EDivisive::new(0.05, 50).with_shuffle(Shuffle::Blocks(100));
+
+        assert!(e_divisive.get_change_points(&series).is_empty());
+    }
+
+    #[test]
+    fn test_autocorrelated_window_shorter_than_blocks() {
+        // Shuffling points would split this smooth curve, as if each point were independent.
+        let series: Vec<f64> = (0..25).map(|i| (i as f64 / 4.0).sin()).collect();
+        let e_divisive = EDivisive::new(0.05, 50).with_shuffle(Shuffle::Blocks(10));
+
+        assert!(e_divisive.get_change_points(&series).is_empty());
+    }
+
+    #[test]
+    fn test_median_statistic() {
+        let mut series: Vec<f64> = (0..30).map(|i| (i % 3) as f64).collect();
+        series.extend((0..30).map(|i| 10.0 + (i % 3) as f64));
+        series[10] = 100.0;
+        let e_divisive = EDivisive::new(0.05, 50).with_statistic(Statistic::Median);
+
+        assert_eq!(e_divisive.get_change_points(&series), vec![30]);
+    }
 }
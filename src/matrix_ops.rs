@@ -1,11 +1,11 @@
 use ndarray::prelude::*;
 use ndarray::{Array, Array2};
 
-fn diff_row(series: &Vec<f64>, value: f64) -> Vec<f64> {
+fn diff_row(series: &[f64], value: f64) -> Vec<f64> {
     series.iter().map(|s| (*s - value).abs()).collect()
 }
 
-pub fn calc_diff_matrix(series: &Vec<f64>) -> Array2<f64> {
+pub fn calc_diff_matrix(series: &[f64]) -> Array2<f64> {
     let series_len = series.len();
     let diff_vectors: Vec<f64> = series.iter().flat_map(|i| diff_row(series, *i)).collect();
 
@@ -24,6 +24,33 @@ pub fn sum_square(
         .sum()
 }
 
+/// Cumulative sums of `matrix`, padded with a leading row and column of zeros, so that any
+/// rectangle can be summed in constant time with `prefix_sum_square`.
+pub fn calc_prefix_matrix(matrix: &ArrayView2<f64>) -> Array2<f64> {
+    let (rows, cols) = matrix.dim();
+    let mut prefix = Array2::zeros((rows + 1, cols + 1));
+    for row in 0..rows {
+        for col in 0..cols {
+            prefix[[row + 1, col + 1]] = matrix[[row, col]] + prefix[[row, col + 1]]
+                + prefix[[row + 1, col]]
+                - prefix[[row, col]];
+        }
+    }
+
+    prefix
+}
+
+pub fn prefix_sum_square(
+    prefix: &ArrayView2<f64>,
+    row_start: usize,
+    row_end: usize,
+    col_start: usize,
+    col_end: usize,
+) -> f64 {
+    prefix[[row_end, col_end]] - prefix[[row_start, col_end]] - prefix[[row_end, col_start]]
+        + prefix[[row_start, col_start]]
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -59,4 +86,20 @@ mod tests {
         assert_eq!(sum_square(&matrix.view(), 0, 2, 0, 2), 8.0);
         assert_eq!(sum_square(&matrix.view(), 1, 3, 1, 3), 16.0);
     }
+
+    #[test]
+    fn test_prefix_sum_square() {
+        let matrix = arr2(&[
+            [1.0, 2.0, 3.0, 4.0],
+            [2.0, 3.0, 4.0, 5.0],
+            [3.0, 4.0, 5.0, 6.0],
+            [4.0, 5.0, 6.0, 7.0],
+        ]);
+        let prefix = calc_prefix_matrix(&matrix.view());
+
+        assert_eq!(prefix_sum_square(&prefix.view(), 0, 1, 0, 1), 1.0);
+        assert_eq!(prefix_sum_square(&prefix.view(), 0, 2, 0, 2), 8.0);
+        assert_eq!(prefix_sum_square(&prefix.view(), 1, 3, 1, 3), 16.0);
+        assert_eq!(prefix_sum_square(&prefix.view(), 1, 3, 0, 4), 32.0);
+    }
 }
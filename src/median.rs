@@ -0,0 +1,193 @@
+use ndarray::ArrayView2;
+
+/// Fenwick tree over distance ranks, used to track how many pairwise distances of each
+/// rank are currently in a set and to pick out order statistics of that set.
+struct RankCounts {
+    tree: Vec<f64>,
+    total: f64,
+}
+
+impl RankCounts {
+    fn new(size: usize) -> RankCounts {
+        RankCounts {
+            tree: vec![0.0; size + 1],
+            total: 0.0,
+        }
+    }
+
+    fn add(&mut self, rank: usize, delta: f64) {
+        self.total += delta;
+        let mut i = rank + 1;
+        while i < self.tree.len() {
+            self.tree[i] += delta;
+            i += i & i.wrapping_neg();
+        }
+    }
+
+    /// Smallest rank whose cumulative count reaches `target`.
+    fn find(&self, target: f64) -> usize {
+        let mut position = 0;
+        let mut remaining = target;
+        let mut step = (self.tree.len() - 1).next_power_of_two();
+        while step > 0 {
+            let next = position + step;
+            if next < self.tree.len() && self.tree[next] < remaining {
+                position = next;
+                remaining -= self.tree[next];
+            }
+            step >>= 1;
+        }
+        position
+    }
+
+    /// Lower weighted median, for sets whose entries carry arbitrary weights.
+    fn weighted_median(&self, values: &[f64]) -> f64 {
+        if self.total <= 0.0 {
+            return 0.0;
+        }
+        values[self.find(self.total / 2.0)]
+    }
+
+    fn median(&self, values: &[f64]) -> f64 {
+        let count = self.total.round() as usize;
+        if count == 0 {
+            return 0.0;
+        }
+        if count.is_multiple_of(2) {
+            let lower = values[self.find((count / 2) as f64)];
+            let upper = values[self.find((count / 2 + 1) as f64)];
+            (lower + upper) / 2.0
+        } else {
+            values[self.find(count.div_ceil(2) as f64)]
+        }
+    }
+}
+
+fn calc_q(cross_median: f64, x_median: f64, y_median: f64, x_len: f64, y_len: f64) -> f64 {
+    if x_len + y_len <= 0.0 {
+        return 0.0;
+    }
+    let factor = (x_len * y_len) / (x_len + y_len);
+    factor * (2.0 * cross_median - x_median - y_median)
+}
+
+/// Like `qhat_values`, but the mean of each set of pairwise distances is replaced by its
+/// median, following E-Divisive with Medians. Every distance is replaced by its rank so the
+/// three sets can be kept in Fenwick trees, which keeps the sweep at O(n^2 log n).
+///
+/// With weights, each distance counts with the product of the weights of its two points,
+/// medians become weighted medians and the size of each side is its total weight.
+pub fn median_qhat_values(diff_matrix: &ArrayView2<f64>, weights: Option<&[f64]>) -> Vec<f64> {
+    let series_len = diff_matrix.nrows();
+    let weight = |i: usize| weights.map_or(1.0, |w| w[i]);
+    let median = |counts: &RankCounts, values: &[f64]| match weights {
+        Some(_) => counts.weighted_median(values),
+        None => counts.median(values),
+    };
+    let total_weight: f64 = (0..series_len).map(weight).sum();
+    let mut x_weight = 0.0;
+
+    let mut values: Vec<f64> = (0..series_len)
+        .flat_map(|i| (i + 1..series_len).map(move |j| (i, j)))
+        .map(|(i, j)| diff_matrix[[i, j]])
+        .collect();
+    values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    values.dedup();
+    let rank = |i: usize, j: usize| {
+        let value = diff_matrix[[i, j]];
+        values.binary_search_by(|v| v.partial_cmp(&value).unwrap()).unwrap()
+    };
+
+    let mut cross = RankCounts::new(values.len());
+    let mut x = RankCounts::new(values.len());
+    let mut y = RankCounts::new(values.len());
+
+    for i in 0..series_len {
+        for j in i + 1..series_len {
+            y.add(rank(i, j), weight(i) * weight(j));
+        }
+    }
+
+    (0..series_len)
+        .map(|tau| {
+            let q = calc_q(
+                median(&cross, &values),
+                median(&x, &values),
+                median(&y, &values),
+                x_weight,
+                total_weight - x_weight,
+            );
+
+            for i in 0..tau {
+                let pair_weight = weight(i) * weight(tau);
+                let r = rank(i, tau);
+                cross.add(r, -pair_weight);
+                x.add(r, pair_weight);
+            }
+            for j in tau + 1..series_len {
+                let pair_weight = weight(tau) * weight(j);
+                let r = rank(tau, j);
+                y.add(r, -pair_weight);
+                cross.add(r, pair_weight);
+            }
+            x_weight += weight(tau);
+
+            q
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix_ops::calc_diff_matrix;
+    use crate::util::median;
+
+    fn naive_median_qhat_values(series: &[f64]) -> Vec<f64> {
+        let n = series.len();
+        let pairs = |a: usize, b: usize, c: usize, d: usize, distinct: bool| {
+            let mut distances = vec![];
+            for i in a..b {
+                for j in c..d {
+                    if !distinct || i < j {
+                        distances.push((series[i] - series[j]).abs());
+                    }
+                }
+            }
+            distances
+        };
+        (0..n)
+            .map(|tau| {
+                calc_q(
+                    median(&pairs(0, tau, tau, n, false)),
+                    median(&pairs(0, tau, 0, tau, true)),
+                    median(&pairs(tau, n, tau, n, true)),
+                    tau as f64,
+                    (n - tau) as f64,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_median_qhat_values() {
+        let series = vec![1.0, 1.5, 1.0, 9.0, 1.2, 5.0, 5.5, 5.0, 4.8, 5.1, 5.0];
+        let diff_matrix = calc_diff_matrix(&series);
+        let q_values = median_qhat_values(&diff_matrix.view(), None);
+
+        for (actual, expected) in q_values.iter().zip(naive_median_qhat_values(&series)) {
+            assert!((actual - expected).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_weighted_median_qhat_values() {
+        let series = vec![1.0, 1.5, 1.0, 1.2, 60.0, 5.0, 5.5, 5.0, 4.8, 5.1];
+        let diff_matrix = calc_diff_matrix(&series);
+        let mut weights = vec![1.0; series.len()];
+        weights[4] = 0.0;
+
+        let q_values = median_qhat_values(&diff_matrix.view(), Some(&weights));
+        assert_eq!(crate::util::argmax(&q_values), 5);
+    }
+}
@@ -0,0 +1,133 @@
+use crate::preprocess::quantile;
+use crate::util::{mad, median};
+
+/// How outliers are recognised.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum OutlierMethod {
+    /// Hampel filter: a point is an outlier when it lies more than the given number of scaled
+    /// MADs from the median of the points up to the given distance on either side of it.
+    Hampel(usize, f64),
+    /// A point is an outlier when it lies more than the given number of scaled MADs from the
+    /// median of the whole series.
+    Mad(f64),
+    /// Tukey's fences: a point is an outlier when it lies more than the given multiple of the
+    /// interquartile range below the first quartile or above the third.
+    Iqr(f64),
+}
+
+/// What to do with the outliers found.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum OutlierAction {
+    /// Report outliers but still run detection over them.
+    Flag,
+    /// Report outliers and leave them out of the detection.
+    Remove,
+}
+
+/// A pass over a series, before segmentation, looking for single-point spikes.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub struct OutlierFilter {
+    method: OutlierMethod,
+    action: OutlierAction,
+}
+
+impl OutlierFilter {
+    pub fn new(method: OutlierMethod, action: OutlierAction) -> OutlierFilter {
+        OutlierFilter { method, action }
+    }
+
+    pub fn action(&self) -> OutlierAction {
+        self.action
+    }
+
+    /// Indexes of the outliers in a series, in order.
+    pub fn find(&self, series: &[f64]) -> Vec<usize> {
+        let is_outlier: Vec<bool> = match self.method {
+            OutlierMethod::Hampel(half_width, threshold) => (0..series.len())
+                .map(|i| {
+                    let end = (i + half_width + 1).min(series.len());
+                    let neighbours = &series[i.saturating_sub(half_width)..end];
+                    beyond(series[i], median(neighbours), mad(neighbours), threshold)
+                })
+                .collect(),
+            OutlierMethod::Mad(threshold) => {
+                let center = median(series);
+                let sigma = mad(series);
+                series.iter().map(|v| beyond(*v, center, sigma, threshold)).collect()
+            }
+            OutlierMethod::Iqr(k) => {
+                let mut sorted = series.to_vec();
+                sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+                let q1 = quantile(&sorted, 0.25);
+                let q3 = quantile(&sorted, 0.75);
+                let iqr = q3 - q1;
+                series
+                    .iter()
+                    .map(|v| iqr > 0.0 && (*v < q1 - k * iqr || *v > q3 + k * iqr))
+                    .collect()
+            }
+        };
+
+        (0..series.len()).filter(|i| is_outlier[*i]).collect()
+    }
+}
+
+/// Whether `value` lies more than `threshold` spreads from `center`. With no spread at all,
+/// as in quantized metrics where most points share a value, nothing counts: any step away
+/// from that value would otherwise be an outlier.
+fn beyond(value: f64, center: f64, spread: f64, threshold: f64) -> bool {
+    spread > 0.0 && (value - center).abs() > threshold * spread
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spiky_step() -> Vec<f64> {
+        let mut series: Vec<f64> = (0..40)
+            .map(|i| (i % 4) as f64 * 0.1 + if i < 20 { 0.0 } else { 5.0 })
+            .collect();
+        series[8] = 30.0;
+        series[31] = -20.0;
+        series
+    }
+
+    #[test]
+    fn test_hampel() {
+        let filter = OutlierFilter::new(OutlierMethod::Hampel(3, 3.0), OutlierAction::Flag);
+        assert_eq!(filter.find(&spiky_step()), vec![8, 31]);
+    }
+
+    #[test]
+    fn test_mad() {
+        let filter = OutlierFilter::new(OutlierMethod::Mad(3.0), OutlierAction::Flag);
+        let series = vec![1.0, 1.2, 0.9, 1.1, 9.0, 1.0, 0.8];
+        assert_eq!(filter.find(&series), vec![4]);
+    }
+
+    #[test]
+    fn test_iqr() {
+        let filter = OutlierFilter::new(OutlierMethod::Iqr(1.5), OutlierAction::Flag);
+        assert_eq!(filter.find(&spiky_step()), vec![8, 31]);
+    }
+
+    #[test]
+    fn test_flat_series_has_no_outliers() {
+        let filter = OutlierFilter::new(OutlierMethod::Hampel(2, 3.0), OutlierAction::Flag);
+        assert!(filter.find(&[2.0; 10]).is_empty());
+    }
+
+    #[test]
+    fn test_quantized_series_has_no_outliers() {
+        let series: Vec<f64> = (0..30).map(|i| if i % 5 == 0 { 11.0 } else { 10.0 }).collect();
+        let methods = [
+            OutlierMethod::Hampel(3, 3.0),
+            OutlierMethod::Mad(3.0),
+            OutlierMethod::Iqr(1.5),
+        ];
+        for method in &methods {
+            let filter = OutlierFilter::new(*method, OutlierAction::Remove);
+            assert!(filter.find(&series).is_empty(), "{:?}", method);
+        }
+    }
+}
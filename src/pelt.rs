@@ -0,0 +1,364 @@
+use std::f64::consts::PI;
+
+use crate::detector::{ChangePoint, ChangePointDetector, Detection};
+use crate::gaps::skip_missing;
+use crate::util::mad;
+
+const DEFAULT_MIN_SEGMENT_LENGTH: usize = 2;
+
+/// Cost of a segment, as twice its negative log-likelihood under a model.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum Cost {
+    /// Normal distribution with a per-segment mean and a variance shared by the whole series.
+    /// The variance is estimated robustly from the differences between consecutive points.
+    NormalMean,
+    /// Normal distribution with a per-segment mean and variance.
+    NormalMeanVar,
+    /// Poisson distribution with a per-segment rate. Values must be non-negative.
+    Poisson,
+}
+
+impl Cost {
+    /// Number of parameters that change at each change point.
+    fn parameters(&self) -> f64 {
+        match self {
+            Cost::NormalMean | Cost::Poisson => 1.0,
+            Cost::NormalMeanVar => 2.0,
+        }
+    }
+}
+
+/// Penalty paid for every change point added to a segmentation.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum Penalty {
+    /// Bayesian information criterion, (p + 1) log n.
+    Bic,
+    /// Modified BIC, (p + 2) log n plus the log length of every segment.
+    Mbic,
+    /// A fixed penalty.
+    Manual(f64),
+}
+
+impl Penalty {
+    /// Penalty per change point for a model where `parameters` change at each change point.
+    pub fn value(&self, parameters: f64, series_len: usize) -> f64 {
+        match *self {
+            Penalty::Bic => (parameters + 1.0) * (series_len as f64).ln(),
+            Penalty::Mbic => (parameters + 2.0) * (series_len as f64).ln(),
+            Penalty::Manual(penalty) => penalty,
+        }
+    }
+}
+
+/// PELT (Pruned Exact Linear Time) change point detection.
+///
+/// Finds the segmentation minimising the total segment cost plus a penalty per change point.
+/// Unlike `EDivisive` it is deterministic and needs no permutation testing.
+pub struct Pelt {
+    cost: Cost,
+    penalty: Penalty,
+    min_segment_length: usize,
+}
+
+impl Default for Pelt {
+    fn default() -> Pelt {
+        Pelt {
+            cost: Cost::NormalMean,
+            penalty: Penalty::Mbic,
+            min_segment_length: DEFAULT_MIN_SEGMENT_LENGTH,
+        }
+    }
+}
+
+impl Pelt {
+    pub fn new(cost: Cost, penalty: Penalty) -> Pelt {
+        Pelt {
+            cost,
+            penalty,
+            ..Pelt::default()
+        }
+    }
+
+    pub fn with_min_segment_length(mut self, min_segment_length: usize) -> Pelt {
+        self.min_segment_length = min_segment_length;
+        self
+    }
+
+    /// Change points of a series, leaving out missing points given as NaN.
+    pub fn get_change_points(&self, series: &[f64]) -> Vec<usize> {
+        let (values, indices) = skip_missing(series);
+        self.find_change_points(&values)
+            .iter()
+            .map(|i| indices[*i])
+            .collect()
+    }
+
+    fn find_change_points(&self, series: &[f64]) -> Vec<usize> {
+        let costs = SegmentCosts::new(series, self.cost, self.penalty == Penalty::Mbic);
+        let penalty = self.penalty.value(self.cost.parameters(), series.len());
+        let min_segment_length = match self.cost {
+            Cost::NormalMeanVar => self.min_segment_length.max(2),
+            _ => self.min_segment_length.max(1),
+        };
+
+        pelt(series.len(), |a, b| costs.cost(a, b), penalty, min_segment_length)
+    }
+}
+
+impl ChangePointDetector for Pelt {
+    fn detect(&self, series: &[f64]) -> Detection {
+        let (series, indices) = skip_missing(series);
+        let costs = SegmentCosts::new(&series, self.cost, false);
+        let indexes = self.find_change_points(&series);
+        let mut bounds = vec![0];
+        bounds.extend(&indexes);
+        bounds.push(series.len());
+
+        Detection::new(
+            bounds
+                .windows(3)
+                .map(|w| ChangePoint {
+                    index: w[1],
+                    score: costs.cost(w[0], w[2])
+                        - costs.cost(w[0], w[1])
+                        - costs.cost(w[1], w[2]),
+                    pvalue: None,
+                })
+                .collect(),
+        )
+        .at_indices(&indices)
+    }
+}
+
+/// Minimise the penalised cost of segmenting `0..series_len` with PELT, returning the
+/// indexes where new segments start. `cost(a, b)` is the cost of the segment `a..b`.
+pub fn pelt<F>(series_len: usize, cost: F, penalty: f64, min_segment_length: usize) -> Vec<usize>
+where
+    F: Fn(usize, usize) -> f64,
+{
+    if series_len < 2 * min_segment_length {
+        return vec![];
+    }
+
+    let mut best = vec![f64::INFINITY; series_len + 1];
+    let mut last_change = vec![0; series_len + 1];
+    let mut candidates: Vec<usize> = vec![];
+    best[0] = -penalty;
+
+    for t in min_segment_length..=series_len {
+        let newest = t - min_segment_length;
+        if newest == 0 || newest >= min_segment_length {
+            candidates.push(newest);
+        }
+
+        let totals: Vec<f64> = candidates
+            .iter()
+            .map(|&tau| best[tau] + cost(tau, t))
+            .collect();
+        let (position, total) = totals
+            .iter()
+            .enumerate()
+            .fold((0, f64::INFINITY), |(p_min, v_min), (p, v)| {
+                if *v < v_min {
+                    (p, *v)
+                } else {
+                    (p_min, v_min)
+                }
+            });
+        best[t] = total + penalty;
+        last_change[t] = candidates[position];
+
+        candidates = candidates
+            .iter()
+            .zip(totals)
+            .filter(|(_, total)| *total <= best[t])
+            .map(|(tau, _)| *tau)
+            .collect();
+    }
+
+    let mut change_points = vec![];
+    let mut t = last_change[series_len];
+    while t > 0 {
+        change_points.push(t);
+        t = last_change[t];
+    }
+    change_points.reverse();
+
+    change_points
+}
+
+struct SegmentCosts {
+    cost: Cost,
+    sum: Vec<f64>,
+    sum_squares: Vec<f64>,
+    variance: f64,
+    segment_length_term: bool,
+}
+
+impl SegmentCosts {
+    fn new(series: &[f64], cost: Cost, segment_length_term: bool) -> SegmentCosts {
+        let mut sum = vec![0.0];
+        let mut sum_squares = vec![0.0];
+        for value in series {
+            sum.push(sum.last().unwrap() + value);
+            sum_squares.push(sum_squares.last().unwrap() + value * value);
+        }
+
+        SegmentCosts {
+            cost,
+            sum,
+            sum_squares,
+            variance: noise_variance(series, 1),
+            segment_length_term,
+        }
+    }
+
+    fn cost(&self, a: usize, b: usize) -> f64 {
+        let n = (b - a) as f64;
+        let sum = self.sum[b] - self.sum[a];
+        let sum_squares = self.sum_squares[b] - self.sum_squares[a];
+        let squared_error = (sum_squares - sum * sum / n).max(0.0);
+
+        let cost = match self.cost {
+            Cost::NormalMean => squared_error / self.variance,
+            Cost::NormalMeanVar => {
+                let variance = (squared_error / n).max(f64::EPSILON);
+                n * ((2.0 * PI).ln() + variance.ln() + 1.0)
+            }
+            Cost::Poisson => {
+                if sum > 0.0 {
+                    2.0 * (sum - sum * (sum / n).ln())
+                } else {
+                    0.0
+                }
+            }
+        };
+
+        if self.segment_length_term {
+            cost + n.ln()
+        } else {
+            cost
+        }
+    }
+}
+
+/// Noise variance estimated from the median absolute deviation of the differences of the
+/// given order, which is barely affected by the level shifts (or, for higher orders, the
+/// trend changes) we are looking for.
+pub fn noise_variance(series: &[f64], order: usize) -> f64 {
+    let mut diffs = series.to_vec();
+    let mut scale = 1.0;
+    for k in 0..order {
+        diffs = diffs.windows(2).map(|w| w[1] - w[0]).collect();
+        // Differencing white noise `order` times scales its variance by C(2 * order, order).
+        scale *= (2 * order - k) as f64 / (k + 1) as f64;
+    }
+    if diffs.len() < 2 {
+        return 1.0;
+    }
+    let sigma = mad(&diffs);
+
+    if sigma > 0.0 {
+        sigma * sigma / scale
+    } else {
+        1.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::noise;
+
+    #[test]
+    fn test_normal_mean() {
+        let series: Vec<f64> = (0..90)
+            .map(|i| noise(i) + if (30..60).contains(&i) { 5.0 } else { 0.0 })
+            .collect();
+        let pelt = Pelt::default();
+
+        assert_eq!(pelt.get_change_points(&series), vec![30, 60]);
+    }
+
+    #[test]
+    fn test_normal_mean_var() {
+        let series: Vec<f64> = (0..100)
+            .map(|i| noise(i) * if i < 50 { 1.0 } else { 20.0 })
+            .collect();
+        let pelt = Pelt::new(Cost::NormalMeanVar, Penalty::Bic);
+
+        assert_eq!(pelt.get_change_points(&series), vec![50]);
+    }
+
+    #[test]
+    fn test_poisson() {
+        let series: Vec<f64> = (0..80)
+            .map(|i| (i % 3) as f64 + if i < 40 { 2.0 } else { 20.0 })
+            .collect();
+        let pelt = Pelt::new(Cost::Poisson, Penalty::Manual(10.0));
+
+        assert_eq!(pelt.get_change_points(&series), vec![40]);
+    }
+
+    #[test]
+    fn test_min_segment_length() {
+        let series = vec![0.0, 0.0, 0.0, 9.0, 0.0, 0.0, 0.0];
+        let pelt = Pelt::new(Cost::NormalMean, Penalty::Manual(0.1)).with_min_segment_length(2);
+
+        for cp in pelt.get_change_points(&series) {
+            assert!(cp >= 2 && cp <= series.len() - 2);
+        }
+    }
+
+    #[test]
+    fn test_missing_points() {
+        let mut series: Vec<f64> = (0..90)
+            .map(|i| noise(i) + if (30..60).contains(&i) { 5.0 } else { 0.0 })
+            .collect();
+        series[10] = f64::NAN;
+        series[45] = f64::NAN;
+        let pelt = Pelt::default();
+
+        assert_eq!(pelt.get_change_points(&series), vec![30, 60]);
+        assert_eq!(pelt.detect(&series).indexes(), vec![30, 60]);
+    }
+
+    #[test]
+    fn test_noise_variance() {
+        let series: Vec<f64> = (0..200)
+            .map(|i| noise(i) + if i < 100 { 0.0 } else { 50.0 })
+            .collect();
+        let ramp: Vec<f64> = (0..200).map(|i| noise(i) + 0.5 * i as f64).collect();
+
+        assert!((noise_variance(&series, 1) - noise_variance(&ramp, 2)).abs() < 0.2);
+        assert!(noise_variance(&series, 1) < 0.5);
+    }
+
+    #[test]
+    fn test_pelt_matches_exhaustive_search() {
+        let series = vec![1.0, 1.2, 0.9, 4.0, 4.1, 3.8, 4.2, 1.0, 1.1, 0.8];
+        let costs = SegmentCosts::new(&series, Cost::NormalMeanVar, false);
+        let cost = |a: usize, b: usize| costs.cost(a, b);
+        let penalty = 3.0;
+
+        let total = |cps: &[usize]| {
+            let mut bounds = vec![0];
+            bounds.extend(cps);
+            bounds.push(series.len());
+            bounds.windows(2).map(|w| cost(w[0], w[1]) + penalty).sum::<f64>()
+        };
+        let found = pelt(series.len(), cost, penalty, 2);
+
+        for mask in 0..(1 << (series.len() - 1)) {
+            let cps: Vec<usize> = (1..series.len())
+                .filter(|i| mask & (1 << (i - 1)) != 0)
+                .collect();
+            let mut bounds = vec![0];
+            bounds.extend(&cps);
+            bounds.push(series.len());
+            if bounds.windows(2).all(|w| w[1] - w[0] >= 2) {
+                assert!(total(&found) <= total(&cps) + 1e-9);
+            }
+        }
+    }
+}
@@ -0,0 +1,202 @@
+use ndarray::{Array2, ArrayView2};
+use rand::prelude::*;
+
+use crate::qhat::Statistic;
+use crate::samples::Samples;
+use crate::search::{best_split, window_intervals};
+use crate::util::maximum;
+
+/// Fewest blocks a window must hold for shuffling blocks to say anything. Shorter windows are
+/// left in order, so that a candidate in them ties with every permutation and is never found
+/// significant: shuffling their points instead would ignore the autocorrelation blocks are
+/// there to keep.
+const MIN_BLOCKS: usize = 3;
+
+/// How a candidate change point is compared against permuted data.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum TestMode {
+    /// Permute every window and compare against the largest qhat found in any of them.
+    Global,
+    /// Permute only the window containing the candidate and compare against its largest qhat.
+    Local,
+}
+
+/// How the points of a window are rearranged for each permutation.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum Shuffle {
+    /// Shuffle individual points.
+    Points,
+    /// Shuffle the order of consecutive blocks of the given length, keeping each block intact.
+    /// Windows too short to hold a few blocks are not shuffled, so no change is found in them.
+    Blocks(usize),
+    /// Rotate the window by a random offset, then shuffle the order of its blocks. Windows too
+    /// short to hold a few blocks are not shuffled, so no change is found in them.
+    CircularBlocks(usize),
+}
+
+impl Shuffle {
+    pub fn shuffled_order<R: Rng>(&self, len: usize, rng: &mut R) -> Vec<usize> {
+        match *self {
+            Shuffle::Blocks(block_len) | Shuffle::CircularBlocks(block_len)
+                if len < MIN_BLOCKS * block_len.max(1) =>
+            {
+                (0..len).collect()
+            }
+            Shuffle::Points => {
+                let mut order: Vec<usize> = (0..len).collect();
+                order.shuffle(rng);
+                order
+            }
+            Shuffle::Blocks(block_len) => shuffle_blocks((0..len).collect(), block_len, rng),
+            Shuffle::CircularBlocks(block_len) => {
+                let offset = if len == 0 { 0 } else { rng.gen_range(0..len) };
+                let rotated = (0..len).map(|i| (i + offset) % len).collect();
+                shuffle_blocks(rotated, block_len, rng)
+            }
+        }
+    }
+}
+
+fn shuffle_blocks<R: Rng>(order: Vec<usize>, block_len: usize, rng: &mut R) -> Vec<usize> {
+    let mut blocks: Vec<&[usize]> = order.chunks(block_len.max(1)).collect();
+    blocks.shuffle(rng);
+    blocks.concat()
+}
+
+/// Probability of a score at least as high as `observed` given the scores of the permuted
+/// data. Ties count against the observed score, so data no permutation can tell apart is never
+/// significant, and the observed data counts as one more permutation, so the p-value is never
+/// zero.
+pub fn permutation_pvalue(observed: f64, permuted: &[f64]) -> f64 {
+    let permutes_with_higher = permuted.iter().filter(|v| **v >= observed).count();
+    (permutes_with_higher + 1) as f64 / (permuted.len() + 1) as f64
+}
+
+pub fn containing_window(windows: &[usize], index: usize) -> &[usize] {
+    let position = windows
+        .windows(2)
+        .position(|bounds| bounds[0] <= index && index < bounds[1])
+        .unwrap_or(0);
+
+    &windows[position..position + 2]
+}
+
+/// Largest score found after shuffling the points of each window. Rather than recomputing
+/// distances, the rows and columns of the window's distance matrix are permuted, moving all
+/// samples of a point together.
+pub fn permutation_test(
+    diff_matrix: &ArrayView2<f64>,
+    samples: &Samples,
+    windows: &[usize],
+    intervals: &[(usize, usize)],
+    shuffle: Shuffle,
+    statistic: Statistic,
+) -> f64 {
+    let mut rng = rand::thread_rng();
+    let mut permuted_qhat_values: Vec<f64> = vec![];
+
+    for bounds in windows.windows(2) {
+        let a = bounds[0];
+        let b = bounds[1];
+
+        let order = shuffle.shuffled_order(b - a, &mut rng);
+        let (sample_order, offsets) = samples.permuted_window(a, &order);
+        let permuted = permute_matrix(diff_matrix, &sample_order);
+        let weights: Option<Vec<f64>> = samples
+            .weights()
+            .map(|w| sample_order.iter().map(|&i| w[i]).collect());
+
+        let best = best_split(
+            &permuted.view(),
+            weights.as_deref(),
+            &offsets,
+            &window_intervals(intervals, a, b),
+            statistic,
+        );
+        permuted_qhat_values.push(best.score);
+    }
+
+    let (_, max_value) = maximum(&permuted_qhat_values);
+    max_value
+}
+
+/// Rows and columns of `matrix` rearranged into `order`.
+pub fn permute_matrix(matrix: &ArrayView2<f64>, order: &[usize]) -> Array2<f64> {
+    let values: Vec<f64> = order
+        .iter()
+        .flat_map(|&i| {
+            let row = matrix.row(i);
+            order.iter().map(move |&j| row[j])
+        })
+        .collect();
+
+    Array2::from_shape_vec((order.len(), order.len()), values).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix_ops::calc_diff_matrix;
+    use itertools::Itertools;
+
+    #[test]
+    fn test_containing_window() {
+        let windows = vec![0, 3, 6, 9, 12];
+        assert_eq!(containing_window(&windows, 0), &[0, 3]);
+        assert_eq!(containing_window(&windows, 4), &[3, 6]);
+        assert_eq!(containing_window(&windows, 9), &[9, 12]);
+    }
+
+    #[test]
+    fn test_permutation_pvalue() {
+        assert_eq!(permutation_pvalue(2.0, &[1.0, 3.0, 0.5]), 0.5);
+        assert_eq!(permutation_pvalue(2.0, &[2.0, 2.0, 2.0]), 1.0);
+        assert_eq!(permutation_pvalue(2.0, &[]), 1.0);
+    }
+
+    #[test]
+    fn test_permute_matrix() {
+        let series = vec![1.0, 4.0, 2.0, 8.0];
+        let order = vec![2, 0, 3, 1];
+        let permuted_series: Vec<f64> = order.iter().map(|&i| series[i]).collect();
+        let diff_matrix = calc_diff_matrix(&series);
+
+        assert_eq!(permute_matrix(&diff_matrix.view(), &order), calc_diff_matrix(&permuted_series));
+    }
+
+    #[test]
+    fn test_shuffled_order_is_permutation() {
+        let mut rng = rand::thread_rng();
+        for shuffle in &[Shuffle::Points, Shuffle::Blocks(3), Shuffle::CircularBlocks(4)] {
+            let order = shuffle.shuffled_order(10, &mut rng);
+            let sorted: Vec<usize> = order.into_iter().sorted().collect();
+            assert_eq!(sorted, (0..10).collect::<Vec<usize>>());
+        }
+    }
+
+    #[test]
+    fn test_short_windows_keep_their_order() {
+        let mut rng = rand::thread_rng();
+        let identity: Vec<usize> = (0..8).collect();
+        for shuffle in &[Shuffle::Blocks(3), Shuffle::CircularBlocks(3)] {
+            assert_eq!(shuffle.shuffled_order(8, &mut rng), identity);
+        }
+    }
+
+    #[test]
+    fn test_blocks_stay_intact() {
+        let mut rng = rand::thread_rng();
+        let order = Shuffle::Blocks(3).shuffled_order(9, &mut rng);
+        for block in order.chunks(3) {
+            assert_eq!(block[0] % 3, 0);
+            assert_eq!(block[1], block[0] + 1);
+            assert_eq!(block[2], block[0] + 2);
+        }
+
+        let order = Shuffle::CircularBlocks(3).shuffled_order(9, &mut rng);
+        for block in order.chunks(3) {
+            assert_eq!(block[1], (block[0] + 1) % 9);
+            assert_eq!(block[2], (block[0] + 2) % 9);
+        }
+    }
+}
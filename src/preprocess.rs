@@ -0,0 +1,270 @@
+use crate::util::median;
+
+/// A transformation applied to a series before detection.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum Transform {
+    /// Natural logarithm. Non-positive values are dropped.
+    Log,
+    /// Subtract the mean and divide by the standard deviation.
+    ZScore,
+    /// Replace each value by its rank, with ties sharing their average rank.
+    Rank,
+    /// Clamp values to the given lower and upper quantiles, each between 0 and 1.
+    Winsorize(f64, f64),
+    /// Difference between each point and the one before it, reported at the later point.
+    Difference,
+    /// Centred moving average over the given number of points, shrinking at the edges.
+    MovingAverage(usize),
+    /// Remove a cycle repeating every given number of points, such as a weekly pattern in
+    /// nightly runs. The phase of each point comes from its index, so points missing from the
+    /// series do not shift the cycle.
+    Deseasonalize(usize),
+}
+
+/// A series after preprocessing, with the index in the original series of each value.
+#[derive(PartialEq, Clone, Debug)]
+pub struct Preprocessed {
+    pub values: Vec<f64>,
+    pub indices: Vec<usize>,
+}
+
+/// A sequence of transforms run, in order, before detection. Change points found on the
+/// transformed series are reported as indexes into the original series, but their scores stay
+/// on the transformed scale.
+#[derive(PartialEq, Clone, Debug, Default)]
+pub struct Preprocessing {
+    steps: Vec<Transform>,
+}
+
+impl Preprocessing {
+    pub fn new() -> Preprocessing {
+        Preprocessing::default()
+    }
+
+    pub fn then(mut self, step: Transform) -> Preprocessing {
+        self.steps.push(step);
+        self
+    }
+
+    /// The transformed series. Values that are not finite, such as NaN marking a missing
+    /// point, are dropped before the first transform, as is anything a transform cannot handle.
+    pub fn apply(&self, series: &[f64]) -> Preprocessed {
+        self.apply_indexed(series, &(0..series.len()).collect::<Vec<usize>>())
+    }
+
+    /// Like `apply`, for values taken from a longer series, such as one with gaps, where
+    /// `indices` holds the index of each value in that series.
+    pub fn apply_indexed(&self, series: &[f64], indices: &[usize]) -> Preprocessed {
+        let mut processed = finite(series.to_vec(), indices.to_vec());
+        for step in &self.steps {
+            processed = apply_step(*step, processed);
+        }
+
+        processed
+    }
+}
+
+fn apply_step(step: Transform, processed: Preprocessed) -> Preprocessed {
+    let Preprocessed { values, indices } = processed;
+    let (values, indices) = match step {
+        Transform::Log => (values.iter().map(|v| v.ln()).collect(), indices),
+        Transform::ZScore => (z_score(&values), indices),
+        Transform::Rank => (rank(&values), indices),
+        Transform::Winsorize(lower, upper) => (winsorize(&values, lower, upper), indices),
+        Transform::Difference => (
+            values.windows(2).map(|w| w[1] - w[0]).collect(),
+            indices.into_iter().skip(1).collect(),
+        ),
+        Transform::MovingAverage(window) => (moving_average(&values, window), indices),
+        Transform::Deseasonalize(period) => (deseasonalize(&values, &indices, period), indices),
+    };
+
+    // Drop anything the transform could not handle, such as the log of a negative value.
+    finite(values, indices)
+}
+
+fn finite(values: Vec<f64>, indices: Vec<usize>) -> Preprocessed {
+    let (values, indices) = values
+        .into_iter()
+        .zip(indices)
+        .filter(|(v, _)| v.is_finite())
+        .unzip();
+    Preprocessed { values, indices }
+}
+
+fn z_score(values: &[f64]) -> Vec<f64> {
+    let n = values.len().max(1) as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    let sd = (values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n).sqrt();
+    let sd = if sd > 0.0 { sd } else { 1.0 };
+
+    values.iter().map(|v| (v - mean) / sd).collect()
+}
+
+fn rank(values: &[f64]) -> Vec<f64> {
+    let mut order: Vec<usize> = (0..values.len()).collect();
+    order.sort_by(|a, b| values[*a].partial_cmp(&values[*b]).unwrap());
+
+    let mut ranks = vec![0.0; values.len()];
+    let mut start = 0;
+    while start < order.len() {
+        let mut end = start + 1;
+        while end < order.len() && values[order[end]] == values[order[start]] {
+            end += 1;
+        }
+        let average = (start + end + 1) as f64 / 2.0;
+        for i in &order[start..end] {
+            ranks[*i] = average;
+        }
+        start = end;
+    }
+
+    ranks
+}
+
+/// Quantile of already sorted values, interpolating between neighbouring values.
+pub fn quantile(sorted: &[f64], q: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let position = q.clamp(0.0, 1.0) * (sorted.len() - 1) as f64;
+    let below = position.floor() as usize;
+    let above = position.ceil() as usize;
+
+    sorted[below] + (position - below as f64) * (sorted[above] - sorted[below])
+}
+
+fn winsorize(values: &[f64], lower: f64, upper: f64) -> Vec<f64> {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let low = quantile(&sorted, lower);
+    let high = quantile(&sorted, upper);
+
+    values.iter().map(|v| v.max(low).min(high)).collect()
+}
+
+fn moving_average(values: &[f64], window: usize) -> Vec<f64> {
+    let before = window.saturating_sub(1) / 2;
+    let after = window.saturating_sub(1) - before;
+
+    (0..values.len())
+        .map(|i| {
+            let neighbours = &values[i.saturating_sub(before)..(i + after + 1).min(values.len())];
+            neighbours.iter().sum::<f64>() / neighbours.len() as f64
+        })
+        .collect()
+}
+
+/// Subtract the seasonal component of a series. The component at each phase of the cycle is
+/// the median deviation of that phase from a moving average over one period, so that level
+/// shifts, which move every phase alike, are left in place.
+fn deseasonalize(values: &[f64], indices: &[usize], period: usize) -> Vec<f64> {
+    if period < 2 || values.len() < 2 * period {
+        return values.to_vec();
+    }
+    let level = moving_average(values, period);
+
+    let mut deviations = vec![vec![]; period];
+    for (i, index) in indices.iter().enumerate() {
+        deviations[index % period].push(values[i] - level[i]);
+    }
+    let seasonal: Vec<f64> = deviations.iter().map(|d| median(d)).collect();
+    let offset = seasonal.iter().sum::<f64>() / period as f64;
+
+    values
+        .iter()
+        .zip(indices)
+        .map(|(v, index)| v - (seasonal[index % period] - offset))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_log_drops_non_positive() {
+        let processed = Preprocessing::new()
+            .then(Transform::Log)
+            .apply(&[1.0, -1.0, std::f64::consts::E]);
+
+        assert_eq!(processed.values, vec![0.0, 1.0]);
+        assert_eq!(processed.indices, vec![0, 2]);
+    }
+
+    #[test]
+    fn test_z_score() {
+        assert_eq!(z_score(&[1.0, 3.0]), vec![-1.0, 1.0]);
+        assert_eq!(z_score(&[2.0, 2.0]), vec![0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_rank() {
+        assert_eq!(rank(&[10.0, 30.0, 20.0, 10.0]), vec![1.5, 4.0, 3.0, 1.5]);
+    }
+
+    #[test]
+    fn test_winsorize() {
+        let values: Vec<f64> = (0..11).map(|i| i as f64).collect();
+        let clamped = winsorize(&values, 0.1, 0.8);
+
+        assert_eq!(clamped[0], 1.0);
+        assert_eq!(clamped[5], 5.0);
+        assert_eq!(clamped[10], 8.0);
+    }
+
+    #[test]
+    fn test_missing_points_are_dropped() {
+        for step in &[Transform::Rank, Transform::Winsorize(0.1, 0.9)] {
+            let processed = Preprocessing::new().then(*step).apply(&[3.0, f64::NAN, 1.0, 2.0]);
+            assert_eq!(processed.indices, vec![0, 2, 3]);
+        }
+    }
+
+    #[test]
+    fn test_difference_maps_to_later_point() {
+        let processed = Preprocessing::new()
+            .then(Transform::Difference)
+            .apply(&[1.0, 4.0, 6.0]);
+
+        assert_eq!(processed.values, vec![3.0, 2.0]);
+        assert_eq!(processed.indices, vec![1, 2]);
+    }
+
+    #[test]
+    fn test_moving_average() {
+        assert_eq!(moving_average(&[3.0, 6.0, 9.0, 0.0], 3), vec![4.5, 6.0, 5.0, 4.5]);
+        assert_eq!(moving_average(&[3.0, 6.0], 1), vec![3.0, 6.0]);
+    }
+
+    #[test]
+    fn test_deseasonalize_uses_original_indices() {
+        let weekly = [0.0, 1.0, 1.0, 1.0, 1.0, -1.0, -3.0];
+        let indices: Vec<usize> = (0..70).filter(|i| !(20..23).contains(i)).collect();
+        let series: Vec<f64> = indices.iter().map(|i| 10.0 + weekly[i % 7]).collect();
+        let processed = Preprocessing::new()
+            .then(Transform::Deseasonalize(7))
+            .apply_indexed(&series, &indices);
+
+        assert_eq!(processed.indices, indices);
+        for v in processed.values {
+            assert!((v - 10.0).abs() < 0.2, "{}", v);
+        }
+    }
+
+    #[test]
+    fn test_deseasonalize() {
+        let weekly = [0.0, 1.0, 1.0, 1.0, 1.0, -1.0, -3.0];
+        let series: Vec<f64> = (0..70)
+            .map(|i| weekly[i % 7] + if i < 35 { 10.0 } else { 20.0 })
+            .collect();
+        let processed = Preprocessing::new()
+            .then(Transform::Deseasonalize(7))
+            .apply(&series);
+
+        for (i, v) in processed.values.iter().enumerate() {
+            let level = if i < 35 { 10.0 } else { 20.0 };
+            assert!((v - level).abs() < 1e-9, "{} at {}", v, i);
+        }
+    }
+}
@@ -1,8 +1,78 @@
-use ndarray::ArrayView2;
+use std::ops::Range;
 
-use crate::matrix_ops::{calc_diff_matrix, sum_square};
+use ndarray::{s, Array2, ArrayView2};
 
-fn calc_q(cross_term: f64, x_term: f64, y_term: f64, x_len: usize, y_len: usize) -> f64 {
+use crate::matrix_ops::sum_square;
+use crate::median::median_qhat_values;
+use crate::util::maximum;
+
+/// The statistic used to score every possible split of a window.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum Statistic {
+    /// The energy statistic of E-Divisive Means, built from mean pairwise distances.
+    Mean,
+    /// The robust E-Divisive with Medians statistic, built from median pairwise distances.
+    Median,
+}
+
+impl Statistic {
+    /// Score of every split, optionally weighting each point of the matrix.
+    pub fn values(&self, diff_matrix: &ArrayView2<f64>, weights: Option<&[f64]>) -> Vec<f64> {
+        match (self, weights) {
+            (Statistic::Mean, None) => qhat_values(diff_matrix),
+            (Statistic::Mean, Some(weights)) => weighted_qhat_values(diff_matrix, weights),
+            (Statistic::Median, _) => median_qhat_values(diff_matrix, weights),
+        }
+    }
+}
+
+/// The qhat score of every split of a range of a series.
+#[derive(PartialEq, Clone, Debug)]
+pub struct QhatCurve {
+    range: Range<usize>,
+    values: Vec<f64>,
+}
+
+impl QhatCurve {
+    pub(crate) fn new(
+        diff_matrix: &ArrayView2<f64>,
+        range: Range<usize>,
+        statistic: Statistic,
+    ) -> QhatCurve {
+        let window = diff_matrix.slice(s![range.clone(), range.clone()]);
+        QhatCurve {
+            values: statistic.values(&window, None),
+            range,
+        }
+    }
+
+    /// The range of the series the curve covers.
+    pub fn range(&self) -> Range<usize> {
+        self.range.clone()
+    }
+
+    /// Scores for splitting before each index of the range, in order.
+    pub fn values(&self) -> &[f64] {
+        &self.values
+    }
+
+    /// Score for splitting the range so that `index` is the first point after the change.
+    pub fn qhat_at(&self, index: usize) -> Option<f64> {
+        if self.range.contains(&index) {
+            Some(self.values[index - self.range.start])
+        } else {
+            None
+        }
+    }
+
+    /// Index of the best split and its score.
+    pub fn best(&self) -> (usize, f64) {
+        let (index, qhat) = maximum(&self.values);
+        (index + self.range.start, qhat)
+    }
+}
+
+pub fn calc_q(cross_term: f64, x_term: f64, y_term: f64, x_len: usize, y_len: usize) -> f64 {
     let x_len = x_len as f64;
     let y_len = y_len as f64;
 
@@ -24,7 +94,7 @@ fn calc_q(cross_term: f64, x_term: f64, y_term: f64, x_len: usize, y_len: usize)
         y_term * (2.0 / (y_len * (y_len - 1.0)))
     };
 
-    let factor = (x_len * y_len as f64) / (x_len + y_len as f64);
+    let factor = (x_len * y_len) / (x_len + y_len);
     factor * (cross_term_reg - x_term_reg - y_term_reg)
 }
 
@@ -62,20 +132,134 @@ pub fn qhat_values(diff_matrix: &ArrayView2<f64>) -> Vec<f64> {
         .collect()
 }
 
-pub fn get_qhat_values(series: &Vec<f64>) -> Vec<f64> {
-    let diff_matrix = calc_diff_matrix(series);
-    qhat_values(&diff_matrix.view())
+/// Like `calc_q`, but with the size of each side given as the total weight of its points and
+/// the number of pairs within each side as the total weight of those pairs.
+fn calc_weighted_q(
+    cross_term: f64,
+    x_term: f64,
+    y_term: f64,
+    x_weight: f64,
+    y_weight: f64,
+    x_pair_weight: f64,
+    y_pair_weight: f64,
+) -> f64 {
+    if x_weight + y_weight <= 0.0 {
+        return 0.0;
+    }
+
+    let cross_term_reg = if x_weight <= 0.0 || y_weight <= 0.0 {
+        0.0
+    } else {
+        cross_term * (2.0 / (x_weight * y_weight))
+    };
+
+    let x_term_reg = if x_pair_weight <= 0.0 {
+        0.0
+    } else {
+        x_term / x_pair_weight
+    };
+
+    let y_term_reg = if y_pair_weight <= 0.0 {
+        0.0
+    } else {
+        y_term / y_pair_weight
+    };
+
+    let factor = (x_weight * y_weight) / (x_weight + y_weight);
+    factor * (cross_term_reg - x_term_reg - y_term_reg)
+}
+
+/// `qhat_values` where every distance `|Xi - Yj|` counts with weight `wi * wj` and the size of
+/// each side is the total weight of its points.
+pub fn weighted_qhat_values(diff_matrix: &ArrayView2<f64>, weights: &[f64]) -> Vec<f64> {
+    let series_len = diff_matrix.nrows();
+    let weighted_matrix = Array2::from_shape_fn((series_len, series_len), |(i, j)| {
+        weights[i] * weights[j] * diff_matrix[[i, j]]
+    });
+    let weighted_matrix = weighted_matrix.view();
+
+    let mut cross_term = 0.0;
+    let mut x_term = 0.0;
+    let mut y_term = 0.0;
+    for row in 0..series_len {
+        y_term += sum_square(&weighted_matrix, row, row + 1, row, series_len);
+    }
+
+    // Total weight of each side, and total weight of the pairs within each side.
+    let total_weight: f64 = weights.iter().sum();
+    let total_squared: f64 = weights.iter().map(|w| w * w).sum();
+    let mut x_weight = 0.0;
+    let mut x_squared = 0.0;
+
+    (0..series_len)
+        .map(|tau| {
+            let y_weight = total_weight - x_weight;
+            let y_squared = total_squared - x_squared;
+            let q = calc_weighted_q(
+                cross_term,
+                x_term,
+                y_term,
+                x_weight,
+                y_weight,
+                (x_weight * x_weight - x_squared) / 2.0,
+                (y_weight * y_weight - y_squared) / 2.0,
+            );
+
+            let column_delta = sum_square(&weighted_matrix, 0, tau, tau, tau + 1);
+            let row_delta = sum_square(&weighted_matrix, tau, tau + 1, tau, series_len);
+
+            cross_term = cross_term - column_delta + row_delta;
+            x_term += column_delta;
+            y_term -= row_delta;
+            x_weight += weights[tau];
+            x_squared += weights[tau] * weights[tau];
+
+            q
+        })
+        .collect()
 }
 
-// #[cfg(test)]
-// mod tests {
-//     use super::*;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix_ops::calc_diff_matrix;
 
-//     #[test]
-//     fn test_series() {
-//         let series = vec![1.0, 1.0, 1.0, 1.0, 2.0, 2.0, 2.0, 2.0, 2.0, 3.0, 3.0, 3.0];
-//         let q_values = get_qhat_values(&series);
+    #[test]
+    fn test_qhat_curve() {
+        let series = vec![5.0, 5.0, 1.0, 1.0, 1.0, 3.0, 3.0, 3.0, 9.0];
+        let diff_matrix = calc_diff_matrix(&series);
+        let curve = QhatCurve::new(&diff_matrix.view(), 2..8, Statistic::Mean);
+        let window = calc_diff_matrix(&series[2..8]);
 
-//         assert_eq!(q_values, vec!(0.0, 0.0));
-//     }
-// }
+        assert_eq!(curve.values(), &qhat_values(&window.view())[..]);
+        assert_eq!(curve.qhat_at(5), Some(curve.values()[3]));
+        assert_eq!(curve.qhat_at(1), None);
+        assert_eq!(curve.qhat_at(8), None);
+        assert_eq!(curve.best().0, 5);
+    }
+
+    #[test]
+    fn test_weighted_qhat_values() {
+        let series = vec![5.0, 5.0, 1.0, 1.0, 1.0, 3.0, 3.0, 3.0, 9.0];
+        let diff_matrix = calc_diff_matrix(&series);
+        let unweighted = qhat_values(&diff_matrix.view());
+        let weighted = weighted_qhat_values(&diff_matrix.view(), &[1.0; 9]);
+
+        for (a, b) in unweighted.iter().zip(&weighted) {
+            assert!((a - b).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_weight_as_repeated_point() {
+        // Up to the normalisation of the within-side terms, a weight of 2 counts a point twice.
+        let series = vec![1.0, 2.0, 8.0, 9.0];
+        let diff_matrix = calc_diff_matrix(&series);
+        let weighted = weighted_qhat_values(&diff_matrix.view(), &[1.0, 1.0, 2.0, 1.0]);
+        let zero_weight = weighted_qhat_values(&diff_matrix.view(), &[1.0, 0.0, 1.0, 1.0]);
+        let without_point = qhat_values(&calc_diff_matrix(&[1.0, 8.0, 9.0]).view());
+
+        assert_eq!(crate::util::argmax(&weighted), 2);
+        assert!((zero_weight[2] - without_point[1]).abs() < 1e-9);
+    }
+}
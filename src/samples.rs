@@ -0,0 +1,202 @@
+use std::ops::Range;
+
+/// The observations of a series, where each point holds one or more samples, such as the
+/// repeated runs of a benchmark on a single commit.
+///
+/// Pairwise distances are taken between individual samples, but change points can only fall
+/// between points and permutations move whole points.
+#[derive(PartialEq, Clone, Debug)]
+pub struct Samples {
+    values: Vec<f64>,
+    offsets: Vec<usize>,
+    weights: Option<Vec<f64>>,
+}
+
+impl Samples {
+    /// One sample per point. None of them may be NaN.
+    pub fn from_series(series: &[f64]) -> Samples {
+        assert!(series.iter().all(|v| !v.is_nan()), "Expected no NaN samples");
+        Samples {
+            values: series.to_vec(),
+            offsets: (0..=series.len()).collect(),
+            weights: None,
+        }
+    }
+
+    /// Several samples per point. Every point must hold at least one sample, and none of them
+    /// may be NaN.
+    pub fn from_replicates(replicates: &[Vec<f64>]) -> Samples {
+        assert!(
+            replicates.iter().all(|point| !point.is_empty()),
+            "Expected at least one sample per point"
+        );
+        assert!(
+            replicates.iter().flatten().all(|v| !v.is_nan()),
+            "Expected no NaN samples"
+        );
+        let mut offsets = vec![0];
+        for point in replicates {
+            offsets.push(offsets.last().unwrap() + point.len());
+        }
+
+        Samples {
+            values: replicates.concat(),
+            offsets,
+            weights: None,
+        }
+    }
+
+    /// Give each point a weight, so that points from shorter runs or noisier hosts have less
+    /// influence on where the series is split. Every sample of a point carries the point's
+    /// weight, and weights are rescaled to average one over the samples.
+    pub fn with_weights(mut self, weights: &[f64]) -> Samples {
+        assert_eq!(weights.len(), self.len(), "Expected one weight per point");
+        let sample_weights: Vec<f64> = weights
+            .iter()
+            .enumerate()
+            .flat_map(|(point, weight)| self.sample_range(point, point + 1).map(move |_| *weight))
+            .collect();
+        self.weights = Some(mean_one(sample_weights));
+        self
+    }
+
+    /// Only the given points, in the order given, with their weights rescaled to average one.
+    pub(crate) fn select(&self, points: &[usize]) -> Samples {
+        let mut selected = Samples {
+            values: vec![],
+            offsets: vec![0],
+            weights: None,
+        };
+        let mut weights = vec![];
+        for point in points {
+            let range = self.sample_range(*point, point + 1);
+            selected.values.extend_from_slice(&self.values[range.clone()]);
+            selected.offsets.push(selected.values.len());
+            if let Some(w) = &self.weights {
+                weights.extend_from_slice(&w[range]);
+            }
+        }
+        if self.weights.is_some() {
+            selected.weights = Some(mean_one(weights));
+        }
+
+        selected
+    }
+
+    /// Number of points.
+    pub fn len(&self) -> usize {
+        self.offsets.len() - 1
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Every sample of every point, in order.
+    pub fn values(&self) -> &[f64] {
+        &self.values
+    }
+
+    /// Weight of every sample, if the points are weighted.
+    pub(crate) fn weights(&self) -> Option<&[f64]> {
+        self.weights.as_deref()
+    }
+
+    /// Range of the samples belonging to the points `a..b`.
+    pub(crate) fn sample_range(&self, a: usize, b: usize) -> Range<usize> {
+        self.offsets[a]..self.offsets[b]
+    }
+
+    /// Start of each point in `a..b`, and the end of the last, relative to the first sample
+    /// of point `a`.
+    pub(crate) fn window_offsets(&self, a: usize, b: usize) -> Vec<usize> {
+        self.offsets[a..=b]
+            .iter()
+            .map(|offset| offset - self.offsets[a])
+            .collect()
+    }
+
+    /// Sample order and relative offsets after rearranging the points `a..b` into `order`,
+    /// which holds positions relative to `a`.
+    pub(crate) fn permuted_window(&self, a: usize, order: &[usize]) -> (Vec<usize>, Vec<usize>) {
+        let mut sample_order = vec![];
+        let mut offsets = vec![0];
+        for point in order {
+            sample_order.extend(self.sample_range(a + point, a + point + 1));
+            offsets.push(sample_order.len());
+        }
+
+        (sample_order, offsets)
+    }
+}
+
+fn mean_one(weights: Vec<f64>) -> Vec<f64> {
+    let mean = weights.iter().sum::<f64>() / weights.len().max(1) as f64;
+    if mean > 0.0 {
+        weights.iter().map(|w| w / mean).collect()
+    } else {
+        weights
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_series() {
+        let samples = Samples::from_series(&[1.0, 2.0, 3.0]);
+        assert_eq!(samples.len(), 3);
+        assert_eq!(samples.window_offsets(1, 3), vec![0, 1, 2]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Expected at least one sample per point")]
+    fn test_from_replicates_rejects_empty_points() {
+        Samples::from_replicates(&[vec![1.0], vec![]]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Expected no NaN samples")]
+    fn test_from_replicates_rejects_nan() {
+        Samples::from_replicates(&[vec![1.0], vec![f64::NAN, 2.0]]);
+    }
+
+    #[test]
+    fn test_from_replicates() {
+        let samples = Samples::from_replicates(&[vec![1.0, 1.1], vec![2.0], vec![3.0, 3.1, 2.9]]);
+
+        assert_eq!(samples.len(), 3);
+        assert_eq!(samples.values(), &[1.0, 1.1, 2.0, 3.0, 3.1, 2.9]);
+        assert_eq!(samples.sample_range(1, 3), 2..6);
+        assert_eq!(samples.window_offsets(1, 3), vec![0, 1, 4]);
+    }
+
+    #[test]
+    fn test_with_weights() {
+        let samples =
+            Samples::from_replicates(&[vec![1.0, 1.1], vec![2.0]]).with_weights(&[1.0, 4.0]);
+        assert_eq!(samples.weights(), Some(&[0.5, 0.5, 2.0][..]));
+    }
+
+    #[test]
+    fn test_select() {
+        let samples = Samples::from_replicates(&[vec![1.0, 1.1], vec![2.0], vec![3.0, 3.1]])
+            .with_weights(&[1.0, 4.0, 2.0]);
+        let selected = samples.select(&[0, 2]);
+
+        assert_eq!(selected.len(), 2);
+        assert_eq!(selected.values(), &[1.0, 1.1, 3.0, 3.1]);
+        assert_eq!(selected.window_offsets(0, 2), vec![0, 2, 4]);
+        assert_eq!(selected.weights(), Some(&[2.0 / 3.0, 2.0 / 3.0, 4.0 / 3.0, 4.0 / 3.0][..]));
+    }
+
+    #[test]
+    fn test_permuted_window() {
+        let samples = Samples::from_replicates(&[vec![1.0, 1.1], vec![2.0], vec![3.0, 3.1, 2.9]]);
+        let (sample_order, offsets) = samples.permuted_window(0, &[2, 0, 1]);
+
+        assert_eq!(sample_order, vec![3, 4, 5, 0, 1, 2]);
+        assert_eq!(offsets, vec![0, 3, 5, 6]);
+    }
+}
@@ -0,0 +1,130 @@
+use ndarray::{s, ArrayView2};
+use rand::Rng;
+
+use crate::detector::ChangePoint;
+use crate::qhat::Statistic;
+use crate::util::argmax;
+
+/// How candidate change points are searched for within each window.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum SearchMode {
+    /// Split each window at the best split of the window as a whole.
+    Greedy,
+    /// Wild Binary Segmentation: also score the given number of random sub-intervals of the
+    /// series and take the best split found in any interval that fits inside the window.
+    /// This finds short segments sandwiched between much longer ones.
+    Wild(usize),
+}
+
+impl SearchMode {
+    /// Draw the sub-intervals `(start, end)` searched in addition to the windows themselves.
+    pub fn intervals<R: Rng>(&self, series_len: usize, rng: &mut R) -> Vec<(usize, usize)> {
+        match *self {
+            SearchMode::Greedy => vec![],
+            SearchMode::Wild(_) if series_len < 3 => vec![],
+            SearchMode::Wild(n_intervals) => (0..n_intervals)
+                .map(|_| {
+                    let start = rng.gen_range(0..series_len - 1);
+                    let end = rng.gen_range(start + 2..=series_len);
+                    (start, end)
+                })
+                .collect(),
+        }
+    }
+}
+
+/// The intervals lying inside the window `a..b`, relative to the start of the window.
+pub fn window_intervals(intervals: &[(usize, usize)], a: usize, b: usize) -> Vec<(usize, usize)> {
+    intervals
+        .iter()
+        .filter(|(start, end)| a <= *start && *end <= b)
+        .map(|(start, end)| (start - a, end - a))
+        .collect()
+}
+
+/// Best split of a window given the distances between its samples, their weights and the
+/// offset at which each of its points starts, searching the whole window and each of
+/// `intervals`. Intervals and the returned index count points from the start of the window.
+pub fn best_split(
+    diff_matrix: &ArrayView2<f64>,
+    weights: Option<&[f64]>,
+    offsets: &[usize],
+    intervals: &[(usize, usize)],
+    statistic: Statistic,
+) -> ChangePoint {
+    let window_len = offsets.len() - 1;
+
+    std::iter::once((0, window_len))
+        .chain(intervals.iter().cloned())
+        .map(|(start, end)| {
+            let (first, last) = (offsets[start], offsets[end]);
+            let sample_qhats = statistic.values(
+                &diff_matrix.slice(s![first..last, first..last]),
+                weights.map(|w| &w[first..last]),
+            );
+            let qhats: Vec<f64> = offsets[start..end]
+                .iter()
+                .map(|offset| sample_qhats[offset - first])
+                .collect();
+            let max_idx = argmax(&qhats);
+            ChangePoint {
+                index: max_idx + start,
+                score: qhats[max_idx],
+                pvalue: None,
+            }
+        })
+        .fold(
+            ChangePoint { index: 0, score: 0.0, pvalue: None },
+            |best, cp| if best.score > cp.score { best } else { cp },
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix_ops::calc_diff_matrix;
+
+    #[test]
+    fn test_intervals() {
+        let mut rng = rand::thread_rng();
+        assert!(SearchMode::Greedy.intervals(100, &mut rng).is_empty());
+
+        let intervals = SearchMode::Wild(50).intervals(100, &mut rng);
+        assert_eq!(intervals.len(), 50);
+        for (start, end) in intervals {
+            assert!(start + 2 <= end && end <= 100);
+        }
+    }
+
+    #[test]
+    fn test_window_intervals() {
+        let intervals = vec![(0, 5), (3, 8), (4, 10), (6, 12)];
+        assert_eq!(window_intervals(&intervals, 2, 10), vec![(1, 6), (2, 8)]);
+    }
+
+    #[test]
+    fn test_best_split_uses_intervals() {
+        let mut series = vec![0.0; 20];
+        series.extend(vec![5.0; 3]);
+        series.extend(vec![0.0; 20]);
+        let diff_matrix = calc_diff_matrix(&series);
+        let offsets: Vec<usize> = (0..=series.len()).collect();
+
+        let whole = best_split(&diff_matrix.view(), None, &offsets, &[], Statistic::Mean);
+        let wild = best_split(&diff_matrix.view(), None, &offsets, &[(15, 23)], Statistic::Mean);
+
+        assert_eq!(wild.index, 20);
+        assert!(wild.score >= whole.score);
+    }
+
+    #[test]
+    fn test_best_split_between_points() {
+        // Three samples per point; the change is between the second and third point.
+        let series = vec![1.0, 1.2, 0.9, 1.1, 1.0, 0.8, 5.0, 5.1, 4.9, 5.2, 5.0, 4.8];
+        let diff_matrix = calc_diff_matrix(&series);
+        let offsets = vec![0, 3, 6, 9, 12];
+
+        let best = best_split(&diff_matrix.view(), None, &offsets, &[], Statistic::Mean);
+        assert_eq!(best.index, 2);
+    }
+}
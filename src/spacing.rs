@@ -0,0 +1,83 @@
+use crate::detector::ChangePoint;
+
+/// How to pick which of two change points that are too close together to keep.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum Strength {
+    /// Keep the change point with the higher score.
+    Score,
+    /// Keep the change point with the lower p-value, breaking ties by score.
+    PValue,
+}
+
+impl Strength {
+    fn is_stronger(&self, a: &ChangePoint, b: &ChangePoint) -> bool {
+        match self {
+            Strength::Score => a.score > b.score,
+            Strength::PValue => {
+                let a_pvalue = a.pvalue.unwrap_or(1.0);
+                let b_pvalue = b.pvalue.unwrap_or(1.0);
+                a_pvalue < b_pvalue || (a_pvalue == b_pvalue && a.score > b.score)
+            }
+        }
+    }
+}
+
+/// Drop the weaker of every pair of neighbouring change points less than `min_distance`
+/// apart, closest pair first, until none are left. The change points kept stay in the order
+/// given.
+pub fn suppress_close(
+    change_points: &[ChangePoint],
+    min_distance: usize,
+    strength: Strength,
+) -> Vec<ChangePoint> {
+    let mut kept = change_points.to_vec();
+    loop {
+        let mut by_index = kept.clone();
+        by_index.sort_by_key(|cp| cp.index);
+        let closest = by_index
+            .windows(2)
+            .filter(|pair| pair[1].index - pair[0].index < min_distance)
+            .min_by_key(|pair| pair[1].index - pair[0].index);
+
+        match closest {
+            Some(pair) => {
+                let weaker = if strength.is_stronger(&pair[0], &pair[1]) {
+                    pair[1].index
+                } else {
+                    pair[0].index
+                };
+                kept.retain(|cp| cp.index != weaker);
+            }
+            None => return kept,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn change_point(index: usize, score: f64, pvalue: f64) -> ChangePoint {
+        ChangePoint { index, score, pvalue: Some(pvalue) }
+    }
+
+    #[test]
+    fn test_suppress_close() {
+        let change_points = vec![
+            change_point(50, 9.0, 0.0),
+            change_point(20, 4.0, 0.01),
+            change_point(21, 3.0, 0.0),
+            change_point(52, 1.0, 0.0),
+        ];
+
+        assert_eq!(
+            suppress_close(&change_points, 3, Strength::Score),
+            vec![change_points[0], change_points[1]]
+        );
+        assert_eq!(
+            suppress_close(&change_points, 3, Strength::PValue),
+            vec![change_points[0], change_points[2]]
+        );
+        assert_eq!(suppress_close(&change_points, 1, Strength::Score), change_points);
+    }
+}
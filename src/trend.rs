@@ -0,0 +1,254 @@
+use crate::detector::{ChangePoint, ChangePointDetector, Detection};
+use crate::gaps::skip_missing;
+use crate::pelt::{noise_variance, pelt, Penalty};
+
+const DEFAULT_MIN_SEGMENT_LENGTH: usize = 3;
+/// Slope and intercept change at each change point.
+const TREND_PARAMETERS: f64 = 2.0;
+
+/// A segment of the series with its least squares line, `value = intercept + slope * index`,
+/// where `index` is the position in the whole series.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub struct TrendSegment {
+    pub start: usize,
+    pub end: usize,
+    pub slope: f64,
+    pub intercept: f64,
+}
+
+/// Detection of changes in linear trend, for regressions that show up as a gradual slowdown
+/// rather than a step.
+///
+/// The series is segmented with PELT into pieces that are each fit by their own line, using
+/// the residual sum of squares scaled by a robust estimate of the noise variance as the cost.
+pub struct TrendDetector {
+    penalty: Penalty,
+    min_segment_length: usize,
+}
+
+impl Default for TrendDetector {
+    fn default() -> TrendDetector {
+        TrendDetector {
+            penalty: Penalty::Mbic,
+            min_segment_length: DEFAULT_MIN_SEGMENT_LENGTH,
+        }
+    }
+}
+
+impl TrendDetector {
+    pub fn new(penalty: Penalty) -> TrendDetector {
+        TrendDetector {
+            penalty,
+            ..TrendDetector::default()
+        }
+    }
+
+    pub fn with_min_segment_length(mut self, min_segment_length: usize) -> TrendDetector {
+        self.min_segment_length = min_segment_length;
+        self
+    }
+
+    /// Change points of a series, leaving out missing points given as NaN.
+    pub fn get_change_points(&self, series: &[f64]) -> Vec<usize> {
+        let (values, indices) = skip_missing(series);
+        self.find_change_points(&values, &indices)
+            .iter()
+            .map(|i| indices[*i])
+            .collect()
+    }
+
+    /// Change points among `series`, the points of a longer series at `indices`.
+    fn find_change_points(&self, series: &[f64], indices: &[usize]) -> Vec<usize> {
+        let fits = LinearFits::new(series, indices, self.penalty == Penalty::Mbic);
+        let penalty = self.penalty.value(TREND_PARAMETERS, series.len());
+
+        pelt(
+            series.len(),
+            |a, b| fits.cost(a, b),
+            penalty,
+            self.min_segment_length.max(2),
+        )
+    }
+
+    /// The segments between the detected change points, with the line fit to each. Missing
+    /// points belong to the segment of the next point present, and a series with no points
+    /// present has no segments.
+    pub fn segments(&self, series: &[f64]) -> Vec<TrendSegment> {
+        let (values, indices) = skip_missing(series);
+        if values.is_empty() {
+            return vec![];
+        }
+        let fits = LinearFits::new(&values, &indices, false);
+        let bound_index = |position: usize| match position {
+            0 => 0,
+            p if p == values.len() => series.len(),
+            p => indices[p],
+        };
+        segment_bounds(&self.find_change_points(&values, &indices), values.len())
+            .windows(2)
+            .map(|w| {
+                let (slope, intercept) = fits.line(w[0], w[1]);
+                TrendSegment {
+                    start: bound_index(w[0]),
+                    end: bound_index(w[1]),
+                    slope,
+                    intercept,
+                }
+            })
+            .collect()
+    }
+}
+
+impl ChangePointDetector for TrendDetector {
+    fn detect(&self, series: &[f64]) -> Detection {
+        let (series, indices) = skip_missing(series);
+        let fits = LinearFits::new(&series, &indices, false);
+        let change_points = self.find_change_points(&series, &indices);
+        let change_points = segment_bounds(&change_points, series.len())
+            .windows(3)
+            .map(|w| ChangePoint {
+                index: w[1],
+                score: fits.cost(w[0], w[2]) - fits.cost(w[0], w[1]) - fits.cost(w[1], w[2]),
+                pvalue: None,
+            })
+            .collect();
+
+        Detection::new(change_points).at_indices(&indices)
+    }
+}
+
+fn segment_bounds(change_points: &[usize], series_len: usize) -> Vec<usize> {
+    let mut bounds = vec![0];
+    bounds.extend(change_points);
+    bounds.push(series_len);
+    bounds
+}
+
+/// Cumulative sums needed to fit a line to any segment in constant time.
+struct LinearFits {
+    x: Vec<f64>,
+    xx: Vec<f64>,
+    y: Vec<f64>,
+    yy: Vec<f64>,
+    xy: Vec<f64>,
+    variance: f64,
+    segment_length_term: bool,
+}
+
+impl LinearFits {
+    /// Fits over `series`, where `indices` holds the index of each value in the whole series.
+    fn new(series: &[f64], indices: &[usize], segment_length_term: bool) -> LinearFits {
+        let mut fits = LinearFits {
+            x: vec![0.0],
+            xx: vec![0.0],
+            y: vec![0.0],
+            yy: vec![0.0],
+            xy: vec![0.0],
+            variance: noise_variance(series, 2),
+            segment_length_term,
+        };
+        for (i, value) in series.iter().enumerate() {
+            let x = indices[i] as f64;
+            fits.x.push(fits.x[i] + x);
+            fits.xx.push(fits.xx[i] + x * x);
+            fits.y.push(fits.y[i] + value);
+            fits.yy.push(fits.yy[i] + value * value);
+            fits.xy.push(fits.xy[i] + x * value);
+        }
+
+        fits
+    }
+
+    /// Centred sums of squares and cross products of the segment `a..b`.
+    fn moments(&self, a: usize, b: usize) -> (f64, f64, f64) {
+        let n = (b - a) as f64;
+        let x = self.x[b] - self.x[a];
+        let y = self.y[b] - self.y[a];
+        let sxx = self.xx[b] - self.xx[a] - x * x / n;
+        let syy = self.yy[b] - self.yy[a] - y * y / n;
+        let sxy = self.xy[b] - self.xy[a] - x * y / n;
+
+        (sxx, syy, sxy)
+    }
+
+    fn line(&self, a: usize, b: usize) -> (f64, f64) {
+        let n = (b - a) as f64;
+        let (sxx, _, sxy) = self.moments(a, b);
+        let slope = if sxx > 0.0 { sxy / sxx } else { 0.0 };
+        let intercept = (self.y[b] - self.y[a] - slope * (self.x[b] - self.x[a])) / n;
+
+        (slope, intercept)
+    }
+
+    fn cost(&self, a: usize, b: usize) -> f64 {
+        let (sxx, syy, sxy) = self.moments(a, b);
+        let explained = if sxx > 0.0 { sxy * sxy / sxx } else { 0.0 };
+        let cost = (syy - explained).max(0.0) / self.variance;
+
+        if self.segment_length_term {
+            cost + ((b - a) as f64).ln()
+        } else {
+            cost
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::noise;
+
+    #[test]
+    fn test_line() {
+        let series: Vec<f64> = (0..10).map(|i| 3.0 + 2.0 * i as f64).collect();
+        let indices: Vec<usize> = (0..series.len()).collect();
+        let fits = LinearFits::new(&series, &indices, false);
+        let (slope, intercept) = fits.line(4, 10);
+
+        assert!((slope - 2.0).abs() < 1e-9);
+        assert!((intercept - 3.0).abs() < 1e-9);
+        assert!(fits.cost(0, 10).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_gradual_slowdown() {
+        // Flat, then a steady slowdown starting after index 60.
+        let series: Vec<f64> = (0..120)
+            .map(|i| 100.0 + noise(i) - if i < 60 { 0.0 } else { 0.5 * (i - 60) as f64 })
+            .collect();
+        let segments = TrendDetector::default().segments(&series);
+
+        assert_eq!(segments.len(), 2);
+        // The point at the kink lies on both lines, so either side of it is a valid split.
+        assert!(segments[0].end == 59 || segments[0].end == 60);
+        assert!(segments[0].slope.abs() < 0.05);
+        assert!((segments[1].slope + 0.5).abs() < 0.05);
+        assert!((segments[1].intercept - 130.0).abs() < 2.0);
+    }
+
+    #[test]
+    fn test_missing_points() {
+        let mut series: Vec<f64> = (0..120)
+            .map(|i| 100.0 + noise(i) - if i < 60 { 0.0 } else { 0.5 * (i - 60) as f64 })
+            .collect();
+        for i in &[5, 70, 71, 72, 119] {
+            series[*i] = f64::NAN;
+        }
+        let segments = TrendDetector::default().segments(&series);
+
+        assert_eq!(segments.len(), 2);
+        assert_eq!(segments[1].end, 120);
+        assert!((segments[1].slope + 0.5).abs() < 0.05);
+        assert!((segments[1].intercept - 130.0).abs() < 2.0);
+        assert_eq!(TrendDetector::default().detect(&series).indexes(), vec![segments[1].start]);
+    }
+
+    #[test]
+    fn test_no_points_present() {
+        let detector = TrendDetector::default();
+
+        assert!(detector.segments(&[]).is_empty());
+        assert!(detector.segments(&[f64::NAN; 3]).is_empty());
+        assert!(detector.detect(&[f64::NAN; 3]).indexes().is_empty());
+    }
+}
@@ -0,0 +1,80 @@
+use ndarray::ArrayView2;
+
+use crate::matrix_ops::{calc_diff_matrix, sum_square};
+use crate::permutation::{permutation_pvalue, permute_matrix, Shuffle};
+use crate::qhat::calc_q;
+
+/// Result of a two-sample energy distance test.
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub struct EnergyTest {
+    /// The energy statistic between the two samples, on the same scale as qhat.
+    pub statistic: f64,
+    /// Share of permutations of the pooled samples that scored at least as high as
+    /// `statistic`, counting the samples as given as one of them.
+    pub pvalue: f64,
+}
+
+/// Test whether two samples, such as a patch build's runs and mainline's recent runs, come
+/// from different distributions.
+pub fn energy_test(x: &[f64], y: &[f64], n_permutations: usize) -> EnergyTest {
+    let mut pooled = x.to_vec();
+    pooled.extend(y);
+    let diff_matrix = calc_diff_matrix(&pooled);
+    let statistic = split_statistic(&diff_matrix.view(), x.len());
+
+    let mut rng = rand::thread_rng();
+    let permuted: Vec<f64> = (0..n_permutations)
+        .map(|_| {
+            let order = Shuffle::Points.shuffled_order(pooled.len(), &mut rng);
+            split_statistic(&permute_matrix(&diff_matrix.view(), &order).view(), x.len())
+        })
+        .collect();
+
+    EnergyTest {
+        statistic,
+        pvalue: permutation_pvalue(statistic, &permuted),
+    }
+}
+
+/// The energy statistic between the first `tau` points and the rest.
+fn split_statistic(diff_matrix: &ArrayView2<f64>, tau: usize) -> f64 {
+    let series_len = diff_matrix.nrows();
+    let cross_term = sum_square(diff_matrix, 0, tau, tau, series_len);
+    let x_term = sum_square(diff_matrix, 0, tau, 0, tau) / 2.0;
+    let y_term = sum_square(diff_matrix, tau, series_len, tau, series_len) / 2.0;
+
+    calc_q(cross_term, x_term, y_term, tau, series_len - tau)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::qhat::qhat_values;
+
+    #[test]
+    fn test_split_statistic_matches_qhat() {
+        let series = vec![1.0, 3.0, 2.0, 7.0, 8.0, 6.5];
+        let diff_matrix = calc_diff_matrix(&series);
+        let qhats = qhat_values(&diff_matrix.view());
+
+        for (tau, qhat) in qhats.iter().enumerate() {
+            assert!((split_statistic(&diff_matrix.view(), tau) - qhat).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_energy_test() {
+        let x: Vec<f64> = (0..20).map(|i| (i % 4) as f64).collect();
+        let same: Vec<f64> = (0..20).map(|i| ((i + 1) % 4) as f64).collect();
+        let shifted: Vec<f64> = x.iter().map(|v| v + 10.0).collect();
+
+        assert!(energy_test(&x, &shifted, 100).pvalue < 0.01);
+        assert!(energy_test(&x, &same, 100).pvalue > 0.2);
+    }
+
+    #[test]
+    fn test_identical_samples() {
+        let constant = vec![5.0; 10];
+        assert_eq!(energy_test(&constant, &constant, 50).pvalue, 1.0);
+    }
+}
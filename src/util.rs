@@ -1,6 +1,8 @@
 use itertools::Itertools;
 
-pub fn maximum(list: &Vec<f64>) -> (usize, f64) {
+const MAD_SCALE: f64 = 1.4826;
+
+pub fn maximum(list: &[f64]) -> (usize, f64) {
     list.iter()
         .enumerate()
         .fold((0, 0.0), |(idx_max, val_max), (idx, val)| {
@@ -12,12 +14,33 @@ pub fn maximum(list: &Vec<f64>) -> (usize, f64) {
         })
 }
 
-pub fn argmax(list: &Vec<f64>) -> usize {
+pub fn argmax(list: &[f64]) -> usize {
     let (max_idx, _) = maximum(list);
     max_idx
 }
 
-pub fn get_windows(change_points: &Vec<usize>, series_len: usize) -> Vec<usize> {
+pub fn median(list: &[f64]) -> f64 {
+    if list.is_empty() {
+        return 0.0;
+    }
+    let mut sorted = list.to_vec();
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let mid = sorted.len() / 2;
+    if sorted.len().is_multiple_of(2) {
+        (sorted[mid - 1] + sorted[mid]) / 2.0
+    } else {
+        sorted[mid]
+    }
+}
+
+/// Median absolute deviation, scaled to estimate the standard deviation of normal data.
+pub fn mad(list: &[f64]) -> f64 {
+    let center = median(list);
+    let deviations: Vec<f64> = list.iter().map(|v| (v - center).abs()).collect();
+    MAD_SCALE * median(&deviations)
+}
+
+pub fn get_windows(change_points: &[usize], series_len: usize) -> Vec<usize> {
     let mut boundaries: Vec<usize> = vec![0];
     boundaries.extend(change_points.iter().sorted());
     if boundaries.last().unwrap() != &series_len {
@@ -27,6 +50,12 @@ pub fn get_windows(change_points: &Vec<usize>, series_len: usize) -> Vec<usize>
     boundaries
 }
 
+/// Small deterministic noise for test fixtures, repeating every seven points.
+#[cfg(test)]
+pub fn noise(i: usize) -> f64 {
+    [0.3, -0.5, 0.1, 0.6, -0.2, -0.4, 0.2][i % 7]
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -43,6 +72,19 @@ mod tests {
         assert_eq!(argmax(&list), 2);
     }
 
+    #[test]
+    fn test_median() {
+        assert_eq!(median(&[3.0, 1.0, 2.0]), 2.0);
+        assert_eq!(median(&[4.0, 1.0, 3.0, 2.0]), 2.5);
+        assert_eq!(median(&[]), 0.0);
+    }
+
+    #[test]
+    fn test_mad() {
+        assert_eq!(mad(&[1.0, 1.0, 1.0]), 0.0);
+        assert!((mad(&[1.0, 2.0, 3.0, 4.0, 100.0]) - 1.4826).abs() < 1e-12);
+    }
+
     #[test]
     fn test_get_windows() {
         let change_points: Vec<usize> = vec![];
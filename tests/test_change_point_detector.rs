@@ -0,0 +1,46 @@
+use edivisive::{Bocpd, ChangePointDetector, EAgglomerative, EDivisive, Pelt, TrendDetector};
+
+fn step_series() -> Vec<f64> {
+    let mut series: Vec<f64> = (0..40).map(|i| (i % 4) as f64).collect();
+    series.extend((0..40).map(|i| 20.0 + (i % 4) as f64));
+    series
+}
+
+#[test]
+fn test_detectors_side_by_side() {
+    let detectors: Vec<(&str, Box<dyn ChangePointDetector>)> = vec![
+        ("e-divisive", Box::new(EDivisive::default())),
+        ("e-agglomerative", Box::new(EAgglomerative::default())),
+        ("pelt", Box::new(Pelt::default())),
+        ("bocpd", Box::new(Bocpd::default())),
+    ];
+    let series = step_series();
+    let mut gappy = step_series();
+    for i in &[5, 39, 41, 70] {
+        gappy[*i] = f64::NAN;
+    }
+
+    for (name, detector) in detectors {
+        assert_eq!(detector.detect(&gappy).indexes(), vec![40], "{} with gaps", name);
+        let detection = detector.detect(&series);
+        assert_eq!(detection.indexes(), vec![40], "{}", name);
+    }
+}
+
+#[test]
+fn test_trend_detector_skips_missing_points() {
+    // Flat for 40 points, then climbing by one per point.
+    let mut series: Vec<f64> = (0..80)
+        .map(|i| (i % 4) as f64 * 0.25 + if i < 40 { 0.0 } else { (i - 40) as f64 })
+        .collect();
+    for i in &[20, 50, 60] {
+        series[*i] = f64::NAN;
+    }
+    let detector = TrendDetector::default();
+    let segments = detector.segments(&series);
+
+    assert_eq!(detector.detect(&series).indexes(), vec![40]);
+    assert_eq!(segments.len(), 2);
+    assert_eq!((segments[0].start, segments[0].end), (0, 40));
+    assert_eq!((segments[1].start, segments[1].end), (40, 80));
+}